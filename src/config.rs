@@ -0,0 +1,155 @@
+use std::{env, fs, io, path::PathBuf};
+
+use tetris::Handling;
+
+use crate::keymap::{Key, Keymap};
+
+const APP_DIR: &str = "tetris-tui-vanilla";
+const CONFIG_FILE: &str = "config.ini";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub keymap: Keymap,
+    pub handling: Handling,
+}
+
+// A missing config file leaves the defaults untouched
+pub fn load(handling: Handling) -> Result<Config, String> {
+    let Some(path) = config_path() else {
+        return parse("", handling);
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => parse(&text, handling).map_err(|msg| format!("{}: {}", path.display(), msg)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => parse("", handling),
+        Err(err) => Err(format!("could not read {}: {}", path.display(), err)),
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join(APP_DIR).join(CONFIG_FILE))
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join(APP_DIR))
+}
+
+fn xdg_dir(var: &str, home: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|dir| PathBuf::from(dir).join(home)))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+}
+
+// INI with a [keys] section of "action = key, key" lines and a [handling] section of frames
+fn parse(text: &str, handling: Handling) -> Result<Config, String> {
+    let mut config = Config {
+        keymap: Keymap::default(),
+        handling,
+    };
+    let mut section = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |msg: String| format!("line {}: {}", i + 1, msg);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = name.trim().to_lowercase();
+            if section != "keys" && section != "handling" {
+                return Err(error(format!("unknown section [{}]", section)));
+            }
+            continue;
+        }
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(error(format!("expected 'name = value', found '{}'", line)));
+        };
+        let (name, value) = (name.trim().to_lowercase(), value.trim());
+        match section.as_str() {
+            "keys" => {
+                let keys = value
+                    .split(',')
+                    .filter(|key| !key.trim().is_empty())
+                    .map(Key::parse)
+                    .collect::<Result<Vec<Key>, String>>()
+                    .map_err(error)?;
+                config.keymap.bind(&name, keys).map_err(error)?;
+            }
+            "handling" => {
+                let frames = value
+                    .parse()
+                    .map_err(|_| error(format!("'{}' is not a number", value)))?;
+                match name.as_str() {
+                    "das" => config.handling.das = frames,
+                    "arr" => config.handling.arr = frames,
+                    "sdf" if frames < 1 => {
+                        return Err(error(String::from("sdf must be at least 1")))
+                    }
+                    "sdf" => config.handling.sdf = frames,
+                    _ => return Err(error(format!("unknown setting '{}'", name))),
+                }
+            }
+            _ => return Err(error(format!("'{}' is outside of a section", name))),
+        }
+    }
+    config.keymap.validate()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tetris::Settings;
+
+    #[test]
+    fn test_parse() {
+        let handling = Settings::default().handling;
+        let text = "
+            # vim style movement
+            [keys]
+            left = h, Left
+            right = l, Right
+            soft_drop = j
+            rotate_cw = k, Up
+            hold = Tab, Comma
+
+            [handling]
+            das = 8
+            arr = 0
+        ";
+        let config = parse(text, handling).unwrap();
+        assert_eq!(config.handling.das, 8);
+        assert_eq!(config.handling.arr, 0);
+        assert_eq!(config.handling.sdf, handling.sdf);
+        assert!(config
+            .keymap
+            .help()
+            .contains(&String::from("[TAB] (or ,) => Hold Block")));
+        assert_eq!(parse("", handling).unwrap().keymap, Keymap::default());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let handling = Settings::default().handling;
+        let error = |text| parse(text, handling).unwrap_err();
+        assert_eq!(error("left = a"), "line 1: 'left' is outside of a section");
+        assert_eq!(error("[keys]\nleft = foo"), "line 2: unknown key 'foo'");
+        assert_eq!(error("[keys]\njump = a"), "line 2: unknown action 'jump'");
+        assert_eq!(
+            error("[handling]\ndas = fast"),
+            "line 2: 'fast' is not a number"
+        );
+        assert_eq!(
+            error("[handling]\nsdf = 0"),
+            "line 2: sdf must be at least 1"
+        );
+        assert_eq!(error("[keys]\nhold ="), "'hold' has no key bound");
+        assert_eq!(
+            error("[keys]\nhard_drop = s"),
+            "S is bound to both 'soft_drop' and 'hard_drop'"
+        );
+    }
+}
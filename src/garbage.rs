@@ -0,0 +1,89 @@
+use rand::Rng;
+
+use crate::{Square, COLS};
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub enum GarbageKind {
+    // Every row has its hole in the same column
+    Clean,
+    // Every row moves its hole away from the one below
+    Messy,
+}
+
+impl GarbageKind {
+    pub fn from_name(name: &str) -> Option<GarbageKind> {
+        match name {
+            "clean" => Some(GarbageKind::Clean),
+            "messy" => Some(GarbageKind::Messy),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GarbageKind::Clean => "clean",
+            GarbageKind::Messy => "messy",
+        }
+    }
+}
+
+// Deals rows of garbage with a single hole, drawing from the game's random numbers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Garbage {
+    kind: GarbageKind,
+    hole: Option<usize>,
+}
+
+impl Garbage {
+    pub fn new(kind: GarbageKind) -> Garbage {
+        Garbage { kind, hole: None }
+    }
+
+    pub fn row<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Vec<Square> {
+        // Sampling u32 rather than usize deals the same holes on 32 and 64 bit targets
+        let hole = match (self.kind, self.hole) {
+            (GarbageKind::Clean, Some(hole)) => hole,
+            (GarbageKind::Messy, Some(last)) => {
+                let hole = rng.gen_range(0..COLS as u32 - 1) as usize;
+                if hole >= last {
+                    hole + 1
+                } else {
+                    hole
+                }
+            }
+            (_, None) => rng.gen_range(0..COLS as u32) as usize,
+        };
+        self.hole = Some(hole);
+        let mut row = vec![Square::Garbage; COLS];
+        row[hole] = Square::Empty;
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn holes(kind: GarbageKind, seed: u64) -> Vec<usize> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut garbage = Garbage::new(kind);
+        (0..20)
+            .map(|_| {
+                let row = garbage.row(&mut rng);
+                assert_eq!(row.iter().filter(|sq| **sq == Square::Empty).count(), 1);
+                row.iter().position(|sq| *sq == Square::Empty).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_garbage_holes() {
+        let clean = holes(GarbageKind::Clean, 3);
+        assert!(clean.iter().all(|hole| *hole == clean[0]));
+        let messy = holes(GarbageKind::Messy, 3);
+        assert!(messy.windows(2).all(|pair| pair[0] != pair[1]));
+        assert_eq!(messy, holes(GarbageKind::Messy, 3));
+    }
+}
@@ -0,0 +1,315 @@
+use std::fmt::{self, Display};
+
+use crossterm::event::{self, KeyCode, KeyModifiers};
+
+use tetris::KeyEvent;
+
+// What a key is bound to: a key of the game, or something only the front end does
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Game(KeyEvent),
+    Scores,
+}
+
+impl Action {
+    // Name used in the config file
+    fn name(&self) -> &'static str {
+        match self {
+            Action::Game(key) => key.name(),
+            Action::Scores => "scores",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "scores" => Some(Action::Scores),
+            _ => KeyEvent::from_name(name).map(Action::Game),
+        }
+    }
+}
+
+impl From<KeyEvent> for Action {
+    fn from(key: KeyEvent) -> Action {
+        Action::Game(key)
+    }
+}
+
+// Actions in the order they are listed in the help screen
+const ACTIONS: [(Action, &str); 12] = [
+    (Action::Game(KeyEvent::Play), "Play"),
+    (Action::Game(KeyEvent::Left), "Move Block to the left"),
+    (Action::Game(KeyEvent::Right), "Move Block to the right"),
+    (Action::Game(KeyEvent::Down), "Move Block down"),
+    (
+        Action::Game(KeyEvent::RotateClockwise),
+        "Rotate Block clockwise",
+    ),
+    (
+        Action::Game(KeyEvent::RotateCounterClockwise),
+        "Rotate Block counter-clockwise",
+    ),
+    (Action::Game(KeyEvent::Rotate180), "Rotate Block 180°"),
+    (Action::Game(KeyEvent::Hold), "Hold Block"),
+    (Action::Game(KeyEvent::HardDrop), "Drop Block"),
+    (Action::Game(KeyEvent::Pause), "Pause"),
+    (Action::Scores, "High scores"),
+    (Action::Game(KeyEvent::Quit), "Quit"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    ctrl: bool,
+}
+
+impl Key {
+    fn new(code: KeyCode) -> Key {
+        Key { code, ctrl: false }
+    }
+
+    pub fn parse(name: &str) -> Result<Key, String> {
+        let lower = name.trim().to_lowercase();
+        if let Some(rest) = lower.strip_prefix("ctrl+") {
+            let key = Key::parse(rest)?;
+            return Ok(Key { ctrl: true, ..key });
+        }
+        let code = match lower.as_str() {
+            "space" => KeyCode::Char(' '),
+            "comma" => KeyCode::Char(','),
+            "enter" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "insert" => KeyCode::Insert,
+            "delete" => KeyCode::Delete,
+            _ => match (lower.chars().next(), lower.chars().count()) {
+                (Some(c), 1) => KeyCode::Char(c),
+                (Some('f'), _) => match lower[1..].parse() {
+                    Ok(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key '{}'", name.trim())),
+                },
+                _ => return Err(format!("unknown key '{}'", name.trim())),
+            },
+        };
+        Ok(Key::new(code))
+    }
+
+    // Letters match regardless of shift or caps lock, Alt and Super combinations are never bound
+    fn from_event(key: &event::KeyEvent) -> Option<Key> {
+        if key
+            .modifiers
+            .intersects(KeyModifiers::ALT | KeyModifiers::SUPER)
+        {
+            return None;
+        }
+        let code = match key.code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        Some(Key {
+            code,
+            ctrl: key.modifiers.contains(KeyModifiers::CONTROL),
+        })
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "CTRL+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "[SPACE]"),
+            KeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => write!(f, "[{}]", format!("{:?}", code).to_uppercase()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<Key>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let char = |c| Key::new(KeyCode::Char(c));
+        let bindings = ACTIONS
+            .iter()
+            .map(|(action, _)| {
+                let keys = match action {
+                    Action::Game(KeyEvent::Play) => vec![char('p'), Key::new(KeyCode::Enter)],
+                    Action::Game(KeyEvent::Left) => vec![char('a'), Key::new(KeyCode::Left)],
+                    Action::Game(KeyEvent::Right) => vec![char('d'), Key::new(KeyCode::Right)],
+                    Action::Game(KeyEvent::Down) => vec![char('s'), Key::new(KeyCode::Down)],
+                    Action::Game(KeyEvent::RotateClockwise) => {
+                        vec![char('w'), Key::new(KeyCode::Up)]
+                    }
+                    Action::Game(KeyEvent::RotateCounterClockwise) => vec![char('z')],
+                    Action::Game(KeyEvent::Rotate180) => vec![char('x')],
+                    Action::Game(KeyEvent::Hold) => vec![char('c')],
+                    Action::Game(KeyEvent::HardDrop) => vec![char(' ')],
+                    Action::Game(KeyEvent::Pause) => vec![Key::new(KeyCode::Esc)],
+                    Action::Scores => vec![char('t')],
+                    Action::Game(KeyEvent::Quit) => vec![
+                        char('q'),
+                        // Raw mode swallows the interrupt signal, so Ctrl+C has to quit by hand
+                        Key {
+                            ctrl: true,
+                            ..char('c')
+                        },
+                    ],
+                };
+                (action.clone(), keys)
+            })
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    pub fn action(&self, key: &event::KeyEvent) -> Option<Action> {
+        let key = Key::from_event(key)?;
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| action.clone())
+    }
+
+    // Replaces the keys of the action with the given config name
+    pub fn bind(&mut self, name: &str, keys: Vec<Key>) -> Result<(), String> {
+        let Some(action) = Action::from_name(name) else {
+            return Err(format!("unknown action '{}'", name));
+        };
+        for (bound, bound_keys) in self.bindings.iter_mut() {
+            if *bound == action {
+                *bound_keys = keys.clone();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, (action, keys)) in self.bindings.iter().enumerate() {
+            if keys.is_empty() {
+                return Err(format!("'{}' has no key bound", action.name()));
+            }
+            for key in keys {
+                if let Some((other, _)) = self.bindings[i + 1..]
+                    .iter()
+                    .find(|(_, other_keys)| other_keys.contains(key))
+                {
+                    return Err(format!(
+                        "{} is bound to both '{}' and '{}'",
+                        key,
+                        action.name(),
+                        other.name()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The first key bound to the action, as shown in the help
+    pub fn key(&self, action: impl Into<Action>) -> String {
+        let action = action.into();
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .and_then(|(_, keys)| keys.first())
+            .map_or(String::from("-"), |key| key.to_string())
+    }
+
+    pub fn help(&self) -> Vec<String> {
+        self.bindings
+            .iter()
+            .map(|(action, keys)| {
+                let description = ACTIONS
+                    .iter()
+                    .find(|(bound, _)| bound == action)
+                    .map_or("", |(_, description)| description);
+                let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+                match keys.split_first() {
+                    Some((first, [])) => format!("{} => {}", first, description),
+                    Some((first, rest)) => {
+                        format!("{} (or {}) => {}", first, rest.join(", "), description)
+                    }
+                    None => format!("- => {}", description),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_lookup() {
+        let keymap = Keymap::default();
+        let key = |code, modifiers| event::KeyEvent::new(code, modifiers);
+        assert_eq!(
+            keymap.action(&key(KeyCode::Char('A'), KeyModifiers::SHIFT)),
+            Some(Action::Game(KeyEvent::Left))
+        );
+        assert_eq!(
+            keymap.action(&key(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(Action::Game(KeyEvent::Quit))
+        );
+        assert_eq!(
+            keymap.action(&key(KeyCode::Char('s'), KeyModifiers::CONTROL)),
+            None
+        );
+        assert_eq!(
+            keymap.action(&key(KeyCode::Esc, KeyModifiers::NONE)),
+            Some(Action::Game(KeyEvent::Pause))
+        );
+        assert_eq!(
+            keymap.action(&key(KeyCode::Char('t'), KeyModifiers::NONE)),
+            Some(Action::Scores)
+        );
+        assert_eq!(keymap.action(&key(KeyCode::F(1), KeyModifiers::NONE)), None);
+    }
+
+    #[test]
+    fn test_bind_and_validate() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.validate(), Ok(()));
+        keymap
+            .bind(
+                "hold",
+                vec![Key::parse("a").unwrap(), Key::parse("F2").unwrap()],
+            )
+            .unwrap();
+        assert_eq!(
+            keymap.validate(),
+            Err(String::from("A is bound to both 'left' and 'hold'"))
+        );
+        keymap.bind("left", vec![Key::parse("j").unwrap()]).unwrap();
+        assert_eq!(keymap.validate(), Ok(()));
+        assert!(keymap
+            .help()
+            .contains(&String::from("A (or F2) => Hold Block")));
+        assert!(keymap.bind("jump", Vec::new()).is_err());
+        assert!(Key::parse("F13").is_err());
+        assert_eq!(
+            Key::parse("ctrl+Space").unwrap().to_string(),
+            "CTRL+[SPACE]"
+        );
+    }
+}
@@ -0,0 +1,2015 @@
+use std::{fmt, fmt::Display};
+
+use garbage::Garbage;
+use master::Master;
+use rand::{distributions::Standard, prelude::Distribution, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use randomizer::Randomizer;
+use scoring::{LineClear, Spin, Statistics};
+
+pub use garbage::GarbageKind;
+pub use randomizer::RandomizerKind;
+pub use replay::{Playback, Replay};
+
+mod garbage;
+mod master;
+mod randomizer;
+mod replay;
+pub mod scoring;
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub struct Coordinates {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl Coordinates {
+    pub fn new(row: usize, col: usize) -> Coordinates {
+        Coordinates { row, col }
+    }
+
+    fn down(mut self, num: usize) -> Coordinates {
+        self.row += num;
+        self
+    }
+
+    fn up(mut self, num: usize) -> Result<Coordinates, ()> {
+        let Some(_num) = self.row.checked_sub(num) else {
+            return Err(());
+        };
+        self.row = _num;
+        Ok(self)
+    }
+
+    fn left(mut self, num: usize) -> Result<Coordinates, ()> {
+        let Some(_num) = self.col.checked_sub(num) else {
+            return Err(());
+        };
+        self.col = _num;
+        Ok(self)
+    }
+
+    fn right(mut self, num: usize) -> Coordinates {
+        self.col += num;
+        self
+    }
+
+    fn offset(mut self, rows: isize, cols: isize) -> Result<Coordinates, ()> {
+        let (Some(row), Some(col)) = (
+            self.row.checked_add_signed(rows),
+            self.col.checked_add_signed(cols),
+        ) else {
+            return Err(());
+        };
+        self.row = row;
+        self.col = col;
+        Ok(self)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub struct Block {
+    pub position: [Coordinates; 4],
+    pub color: Color,
+    pub piece: Piece,
+    pub rotation_pos: usize,
+}
+
+impl Block {
+    pub fn new(piece: Piece) -> Block {
+        let color = piece.color();
+        let coor = Coordinates::new(4, COLS / 2 - 1);
+        let rotation_pos = 0;
+        let position = get_piece_position(piece, rotation_pos, coor).unwrap();
+        Block {
+            position,
+            color,
+            piece,
+            rotation_pos,
+        }
+    }
+
+    fn down(&mut self) {
+        for pos in self.position.iter_mut() {
+            pos.row += 1
+        }
+    }
+
+    fn left(&mut self) {
+        for pos in self.position.iter_mut() {
+            pos.col -= 1
+        }
+    }
+
+    fn right(&mut self) {
+        for pos in self.position.iter_mut() {
+            pos.col += 1
+        }
+    }
+
+    fn up(&mut self) -> Result<(), ()> {
+        if self.position.iter().any(|pos| pos.row == 0) {
+            return Err(());
+        }
+        for pos in self.position.iter_mut() {
+            pos.row -= 1
+        }
+        Ok(())
+    }
+
+    // Every candidate position for the rotation with its kick index, in SRS kick order
+    fn rotate(&self, rotation: KeyEvent) -> Vec<(usize, Block)> {
+        let turns = match rotation {
+            KeyEvent::RotateClockwise => 1,
+            KeyEvent::Rotate180 => 2,
+            KeyEvent::RotateCounterClockwise => 3,
+            _ => return Vec::new(),
+        };
+        let rotation_pos = (self.rotation_pos + turns) % 4;
+        let (from_row, from_col) = pivot_cell(self.piece, self.rotation_pos);
+        let (to_row, to_col) = pivot_cell(self.piece, rotation_pos);
+        get_kicks(self.piece, self.rotation_pos, rotation_pos)
+            .iter()
+            .enumerate()
+            .filter_map(|(kick, (x, y))| {
+                let coor = self.position[0]
+                    .offset(to_row - from_row - y, to_col - from_col + x)
+                    .ok()?;
+                let position = get_piece_position(self.piece, rotation_pos, coor).ok()?;
+                Some((
+                    kick,
+                    Block {
+                        position,
+                        rotation_pos,
+                        ..*self
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    // Spawn position drawn in a 4x2 box
+    fn display(&self) -> Vec<String> {
+        let mut matrix = [[Square::Empty; 4]; 2];
+        for i in 0..4 {
+            matrix[self.position[i].row - 3][self.position[i].col - 3] =
+                Square::Occupied(self.color);
+        }
+        matrix
+            .iter_mut()
+            .map(|val| val.iter().map(|num| num.to_string()).collect::<String>())
+            .collect::<Vec<String>>()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub enum Piece {
+    I,
+    J,
+    L,
+    O,
+    S,
+    T,
+    Z,
+}
+
+impl Piece {
+    pub fn color(&self) -> Color {
+        match self {
+            Piece::I => Color::Brown,
+            Piece::J => Color::Blue,
+            Piece::L => Color::Orange,
+            Piece::O => Color::Yellow,
+            Piece::S => Color::Green,
+            Piece::T => Color::Violet,
+            Piece::Z => Color::Red,
+        }
+    }
+}
+
+impl Distribution<Piece> for Standard {
+    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> Piece {
+        match rng.gen_range(0..=6) {
+            0 => Piece::I,
+            1 => Piece::J,
+            2 => Piece::L,
+            3 => Piece::O,
+            4 => Piece::S,
+            5 => Piece::T,
+            _ => Piece::Z,
+        }
+    }
+}
+
+// Rotation states follow SRS (0, R, 2, L). The first coordinate is always the pivot mino,
+// which for I and O moves around the center of their bounding box (see `pivot_cell`).
+fn get_piece_position(piece: Piece, pos: usize, coor: Coordinates) -> Result<[Coordinates; 4], ()> {
+    match (piece, pos) {
+        (Piece::I, 0) => Ok([coor, coor.left(1)?, coor.right(1), coor.right(2)]),
+        (Piece::I, 1) => Ok([coor, coor.up(1)?, coor.down(1), coor.down(2)]),
+        (Piece::I, 2) => Ok([coor, coor.left(2)?, coor.left(1)?, coor.right(1)]),
+        (Piece::I, 3) => Ok([coor, coor.up(2)?, coor.up(1)?, coor.down(1)]),
+        (Piece::J, 0) => Ok([coor, coor.left(1)?, coor.right(1), coor.up(1)?.left(1)?]),
+        (Piece::J, 1) => Ok([coor, coor.up(1)?, coor.down(1), coor.up(1)?.right(1)]),
+        (Piece::J, 2) => Ok([coor, coor.left(1)?, coor.right(1), coor.down(1).right(1)]),
+        (Piece::J, 3) => Ok([coor, coor.up(1)?, coor.down(1), coor.down(1).left(1)?]),
+        (Piece::L, 0) => Ok([coor, coor.left(1)?, coor.right(1), coor.up(1)?.right(1)]),
+        (Piece::L, 1) => Ok([coor, coor.up(1)?, coor.down(1), coor.down(1).right(1)]),
+        (Piece::L, 2) => Ok([coor, coor.left(1)?, coor.right(1), coor.down(1).left(1)?]),
+        (Piece::L, 3) => Ok([coor, coor.up(1)?, coor.down(1), coor.up(1)?.left(1)?]),
+        (Piece::T, 0) => Ok([coor, coor.left(1)?, coor.right(1), coor.up(1)?]),
+        (Piece::T, 1) => Ok([coor, coor.up(1)?, coor.down(1), coor.right(1)]),
+        (Piece::T, 2) => Ok([coor, coor.left(1)?, coor.right(1), coor.down(1)]),
+        (Piece::T, 3) => Ok([coor, coor.up(1)?, coor.down(1), coor.left(1)?]),
+        (Piece::S, 0) => Ok([coor, coor.left(1)?, coor.up(1)?, coor.up(1)?.right(1)]),
+        (Piece::S, 1) => Ok([coor, coor.up(1)?, coor.right(1), coor.down(1).right(1)]),
+        (Piece::S, 2) => Ok([coor, coor.right(1), coor.down(1), coor.down(1).left(1)?]),
+        (Piece::S, 3) => Ok([coor, coor.left(1)?, coor.up(1)?.left(1)?, coor.down(1)]),
+        (Piece::Z, 0) => Ok([coor, coor.right(1), coor.up(1)?, coor.up(1)?.left(1)?]),
+        (Piece::Z, 1) => Ok([coor, coor.right(1), coor.down(1), coor.up(1)?.right(1)]),
+        (Piece::Z, 2) => Ok([coor, coor.left(1)?, coor.down(1), coor.down(1).right(1)]),
+        (Piece::Z, 3) => Ok([coor, coor.up(1)?, coor.left(1)?, coor.down(1).left(1)?]),
+        (Piece::O, 0) => Ok([coor, coor.right(1), coor.up(1)?, coor.up(1)?.right(1)]),
+        (Piece::O, 1) => Ok([coor, coor.right(1), coor.down(1), coor.down(1).right(1)]),
+        (Piece::O, 2) => Ok([coor, coor.left(1)?, coor.down(1), coor.down(1).left(1)?]),
+        (Piece::O, 3) => Ok([coor, coor.left(1)?, coor.up(1)?, coor.up(1)?.left(1)?]),
+        (_, _) => Err(()),
+    }
+}
+
+// Position of the pivot mino inside the piece bounding box, as (row, col)
+fn pivot_cell(piece: Piece, pos: usize) -> (isize, isize) {
+    match (piece, pos) {
+        (Piece::I, 0) => (1, 1),
+        (Piece::I, 1) => (1, 2),
+        (Piece::I, 2) => (2, 2),
+        (Piece::I, _) => (2, 1),
+        (Piece::O, 0) => (1, 0),
+        (Piece::O, 1) => (0, 0),
+        (Piece::O, 2) => (0, 1),
+        (Piece::O, _) => (1, 1),
+        (_, _) => (1, 1),
+    }
+}
+
+// SRS wall kicks as (x, y) with y pointing up, indexed by the state rotated clockwise from.
+// Counter-clockwise kicks are the negated clockwise kicks of the opposite rotation.
+const JLSTZ_KICKS: [[(isize, isize); 5]; 4] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+
+const I_KICKS: [[(isize, isize); 5]; 4] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+];
+
+// 180 kicks have no official SRS data, these are the widely used SRS+ ones
+const HALF_KICKS: [[(isize, isize); 6]; 4] = [
+    [(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+    [(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+    [(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],
+    [(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)],
+];
+
+fn get_kicks(piece: Piece, from: usize, to: usize) -> Vec<(isize, isize)> {
+    let kicks = match piece {
+        Piece::I => I_KICKS,
+        Piece::O => return vec![(0, 0)],
+        _ => JLSTZ_KICKS,
+    };
+    if to == (from + 1) % 4 {
+        kicks[from].to_vec()
+    } else if from == (to + 1) % 4 {
+        kicks[to].iter().map(|(x, y)| (-x, -y)).collect()
+    } else {
+        HALF_KICKS[from].to_vec()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub enum Color {
+    Red,
+    Blue,
+    Orange,
+    Yellow,
+    Green,
+    Violet,
+    Brown,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub enum Square {
+    Empty,
+    Ghost,
+    Occupied(Color),
+    Garbage,
+}
+
+impl Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Square::Empty => write!(f, "  "),
+            Square::Ghost => write!(f, "\u{2591}\u{2591}"),
+            Square::Occupied(Color::Red) => write!(f, "\u{1F7E5}"),
+            Square::Occupied(Color::Blue) => write!(f, "\u{1F7E6}"),
+            Square::Occupied(Color::Orange) => write!(f, "\u{1F7E7}"),
+            Square::Occupied(Color::Yellow) => write!(f, "\u{1F7E8}"),
+            Square::Occupied(Color::Green) => write!(f, "\u{1F7E9}"),
+            Square::Occupied(Color::Violet) => write!(f, "\u{1F7EA}"),
+            Square::Occupied(Color::Brown) => write!(f, "\u{1F7EB}"),
+            Square::Garbage => write!(f, "\u{2B1C}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GameState {
+    Playing,
+    Pause,
+    Menu,
+    EndScreen,
+    // The mode's line goal was reached
+    Won,
+    // The mode's time ran out
+    Finished,
+}
+
+impl GameState {
+    pub fn is_over(&self) -> bool {
+        matches!(
+            self,
+            GameState::EndScreen | GameState::Won | GameState::Finished
+        )
+    }
+
+    // The keys to go on depend on the frontend, so only the title is shown here
+    fn message(&self) -> &'static str {
+        match self {
+            GameState::Pause => "GAME PAUSED",
+            GameState::EndScreen => "YOU LOST!",
+            GameState::Won => "YOU WON!",
+            GameState::Finished => "TIME'S UP!",
+            GameState::Playing | GameState::Menu => "",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    Down,
+    Left,
+    Right,
+    RotateClockwise,
+    RotateCounterClockwise,
+    Rotate180,
+    Hold,
+    HardDrop,
+    Quit,
+    Play,
+    Pause,
+}
+
+const KEY_EVENTS: [KeyEvent; 11] = [
+    KeyEvent::Down,
+    KeyEvent::Left,
+    KeyEvent::Right,
+    KeyEvent::RotateClockwise,
+    KeyEvent::RotateCounterClockwise,
+    KeyEvent::Rotate180,
+    KeyEvent::Hold,
+    KeyEvent::HardDrop,
+    KeyEvent::Quit,
+    KeyEvent::Play,
+    KeyEvent::Pause,
+];
+
+impl KeyEvent {
+    // Name used in config and replay files
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyEvent::Down => "soft_drop",
+            KeyEvent::Left => "left",
+            KeyEvent::Right => "right",
+            KeyEvent::RotateClockwise => "rotate_cw",
+            KeyEvent::RotateCounterClockwise => "rotate_ccw",
+            KeyEvent::Rotate180 => "rotate_180",
+            KeyEvent::Hold => "hold",
+            KeyEvent::HardDrop => "hard_drop",
+            KeyEvent::Quit => "quit",
+            KeyEvent::Play => "play",
+            KeyEvent::Pause => "pause",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<KeyEvent> {
+        KEY_EVENTS.into_iter().find(|key| key.name() == name)
+    }
+}
+
+// What drives the engine: key presses and releases, and the frame clock
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Press(KeyEvent),
+    Release(KeyEvent),
+    Tick,
+}
+
+// Things that happened during a step, for front ends to react to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    // A piece locked, with the lines it cleared if any
+    Lock(LineClear),
+    Hold(Piece),
+    GameOver,
+    // The mode's goal was reached or its time ran out
+    Finished,
+}
+
+pub const COLS: usize = 10;
+pub const ROWS: usize = 23;
+
+pub const FPS: u32 = 60;
+const LINES_PER_LEVEL: usize = 10;
+// Split times are taken every SPLIT_LINES lines
+pub const SPLIT_LINES: usize = 10;
+pub const MARATHON_LINES: usize = 150;
+// Zen speeds follow the level gravity curve, which stops speeding up at level 20
+pub const MAX_ZEN_SPEED: usize = 20;
+// Rows cleared from the bottom when a Zen stack reaches the top
+const ZEN_CLEARED_ROWS: usize = ROWS / 2;
+// Dig leaves at least the top rows free for the pieces to spawn in
+pub const MAX_DIG_ROWS: usize = 15;
+// Gravity is measured in 1/GRAVITY_UNIT rows per frame
+const GRAVITY_UNIT: u32 = 65536;
+const MAX_GRAVITY: u32 = 20 * GRAVITY_UNIT;
+const LABEL_FRAMES: u32 = 2 * FPS;
+const LOCK_DELAY_FRAMES: u32 = 30;
+const LOCK_RESETS: u32 = 15;
+pub const MAX_PREVIEWS: usize = 6;
+
+// Game time as minutes, seconds and milliseconds
+pub fn format_time(frames: u64) -> String {
+    let millis = frames * 1000 / FPS as u64;
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+// Survival raises a row every 10 seconds at first, a second sooner every 30 seconds, down to 2
+fn rise_interval(frame: u64) -> u64 {
+    let second = FPS as u64;
+    (10 * second)
+        .saturating_sub(frame / (30 * second) * second)
+        .max(2 * second)
+}
+
+// Auto repeat timings, in frames
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub struct Handling {
+    // Delay before a held direction starts repeating
+    pub das: u32,
+    // Delay between repeated moves, 0 moves straight to the wall
+    pub arr: u32,
+    // How many times faster than gravity soft drop falls
+    pub sdf: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub enum GameMode {
+    // Plays until topping out, the classic way
+    Endless,
+    // Clears 40 lines as fast as possible
+    Sprint,
+    // Scores as much as possible in two minutes
+    Ultra,
+    // Clears 150 lines, or as many as the settings ask for, to win
+    Marathon,
+    // Never tops out and never speeds up
+    Zen,
+    // Clears rows of garbage as fast as possible
+    Dig,
+    // Lasts as long as possible against garbage rising faster and faster
+    Survival,
+    // Climbs to level 999 at 20G for a TGM style grade
+    Master,
+}
+
+// Modes in the order they are listed in the menu
+pub const GAME_MODES: [GameMode; 8] = [
+    GameMode::Endless,
+    GameMode::Sprint,
+    GameMode::Ultra,
+    GameMode::Marathon,
+    GameMode::Zen,
+    GameMode::Dig,
+    GameMode::Survival,
+    GameMode::Master,
+];
+
+impl GameMode {
+    pub fn from_name(name: &str) -> Option<GameMode> {
+        GAME_MODES.into_iter().find(|mode| mode.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Endless => "endless",
+            GameMode::Sprint => "sprint",
+            GameMode::Ultra => "ultra",
+            GameMode::Marathon => "marathon",
+            GameMode::Zen => "zen",
+            GameMode::Dig => "dig",
+            GameMode::Survival => "survival",
+            GameMode::Master => "master",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            GameMode::Endless => "Play until you top out",
+            GameMode::Sprint => "Clear 40 lines as fast as possible",
+            GameMode::Ultra => "Score as much as possible in 2 minutes",
+            GameMode::Marathon => "Clear 150 lines to win",
+            GameMode::Zen => "Relax, topping out only clears the bottom rows",
+            GameMode::Dig => "Dig through the garbage as fast as possible",
+            GameMode::Survival => "Hold out while garbage rises faster and faster",
+            GameMode::Master => "Reach level 999 at 20G and earn a grade",
+        }
+    }
+
+    // Lines to clear to finish the game
+    pub fn line_goal(&self) -> Option<usize> {
+        match self {
+            GameMode::Sprint => Some(40),
+            GameMode::Marathon => Some(MARATHON_LINES),
+            _ => None,
+        }
+    }
+
+    // Frames until the game ends
+    pub fn time_limit(&self) -> Option<u64> {
+        match self {
+            GameMode::Ultra => Some(120 * FPS as u64),
+            _ => None,
+        }
+    }
+
+    pub fn rank_by(&self) -> RankBy {
+        match self {
+            GameMode::Sprint | GameMode::Dig => RankBy::Time,
+            GameMode::Survival => RankBy::Survival,
+            _ => RankBy::Points,
+        }
+    }
+
+    pub fn can_top_out(&self) -> bool {
+        *self != GameMode::Zen
+    }
+}
+
+// What puts a game first in the high scores
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub enum RankBy {
+    // The highest score
+    Points,
+    // The fastest finished game
+    Time,
+    // The longest game
+    Survival,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub struct Settings {
+    pub mode: GameMode,
+    pub randomizer: RandomizerKind,
+    // Fixed seed for the piece sequence, a random one for each game otherwise
+    pub seed: Option<u64>,
+    pub previews: usize,
+    pub handling: Handling,
+    // Lines to clear to win Marathon
+    pub marathon_lines: usize,
+    // Gravity of Zen, as the level it is taken from
+    pub zen_speed: usize,
+    // Rows of garbage Dig starts with, and how their holes line up
+    pub dig_rows: usize,
+    pub garbage: GarbageKind,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            mode: GameMode::Endless,
+            randomizer: RandomizerKind::Bag,
+            seed: None,
+            previews: 5,
+            handling: Handling {
+                das: 10,
+                arr: 2,
+                sdf: 20,
+            },
+            marathon_lines: MARATHON_LINES,
+            zen_speed: 1,
+            dig_rows: 10,
+            garbage: GarbageKind::Messy,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+struct AutoShift {
+    left: bool,
+    right: bool,
+    direction: Option<KeyEvent>,
+    frames: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+struct LockDelay {
+    frames: u32,
+    resets: u32,
+    lowest_row: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tetris {
+    settings: Settings,
+    seed: u64,
+    rng: ChaCha8Rng,
+    board: Vec<Vec<Square>>,
+    current_block: Block,
+    randomizer: Randomizer,
+    hold: Option<Piece>,
+    can_hold: bool,
+    gravity: u32,
+    shift: AutoShift,
+    soft_drop: bool,
+    lock_delay: LockDelay,
+    points: usize,
+    lines: usize,
+    level: usize,
+    back_to_back: bool,
+    combo: Option<usize>,
+    last_rotation: Option<(KeyEvent, usize)>,
+    label: Option<(Vec<String>, u32)>,
+    stats: Statistics,
+    state: GameState,
+    events: Vec<Event>,
+    // Frames played so far, and every input stamped with the frame it came in
+    frame: u64,
+    inputs: Vec<(u64, Input)>,
+    // Frame at which every SPLIT_LINES lines were cleared
+    splits: Vec<u64>,
+    garbage: Garbage,
+    // Frame at which Survival raises the next row of garbage
+    next_rise: u64,
+    // Frames left before the next piece spawns, in modes with spawn and line clear delays
+    spawn_delay: u32,
+    master: Master,
+}
+
+impl Display for Tetris {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut output = self.board.clone();
+        // There is no falling piece while the next one waits to spawn
+        if self.spawn_delay == 0 {
+            for coor in self.ghost_block().position {
+                output[coor.row][coor.col] = Square::Ghost;
+            }
+            for i in 0..4 {
+                output[self.current_block.position[i].row][self.current_block.position[i].col] =
+                    Square::Occupied(self.current_block.color);
+            }
+        }
+        let empty = vec![Square::Empty.to_string().repeat(4); 2];
+        let hold = match self.hold {
+            Some(piece) => Block::new(piece).display(),
+            None => empty.clone(),
+        };
+        let next: Vec<Vec<String>> = self
+            .randomizer
+            .preview()
+            .map(|&piece| Block::new(piece).display())
+            .collect();
+        let message = self.state.message();
+        let stats = self.side_panel();
+        let ended = self.state.is_over();
+        let summary = self.stats.summary();
+        let output: Vec<String> = output
+            .iter_mut()
+            .skip(4)
+            .enumerate()
+            .map(|(row, val)| {
+                let ret: Vec<String> = val.iter().map(|num| num.to_string()).collect();
+                let left_menu = match row {
+                    0 => format!("{:<32}", "HOLD"),
+                    1 | 2 => format!("{}{}", hold[row - 1], " ".repeat(24)),
+                    4..=8 => format!("{:<32}", stats.get(row - 4).unwrap_or(&String::new())),
+                    9 => format!("{:<32}", message),
+                    11 if ended => {
+                        format!("{:<32}", format!("Seed: {}", self.seed))
+                    }
+                    12..=17 if ended => {
+                        format!("{:<32}", summary.get(row - 12).unwrap_or(&String::new()))
+                    }
+                    12..=14 => match &self.label {
+                        Some((label, _)) => {
+                            format!("{:<32}", label.get(row - 12).unwrap_or(&String::new()))
+                        }
+                        None => " ".repeat(32),
+                    },
+                    _ => " ".repeat(32),
+                };
+                // The queue takes two rows per piece with a blank row in between
+                let right_menu = match row {
+                    0 => String::from("NEXT    "),
+                    _ if row % 3 != 0 => {
+                        next.get((row - 1) / 3).unwrap_or(&empty)[(row - 1) % 3].clone()
+                    }
+                    _ => " ".repeat(8),
+                };
+                format!(
+                    "\u{2590}{}\u{258C}    {}{}",
+                    ret.join(""),
+                    left_menu,
+                    right_menu
+                )
+            })
+            .collect();
+        write!(
+            f,
+            "{}\n{}",
+            output.join("\n"),
+            "\u{2594}".repeat(COLS * 2 + 2),
+        )
+    }
+}
+
+impl Tetris {
+    pub fn new(settings: Settings) -> Tetris {
+        // ChaCha streams are the same on every platform, so a seed always deals the same pieces
+        let seed = settings.seed.unwrap_or_else(rand::random);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut randomizer = Randomizer::new(settings.randomizer, settings.previews, &mut rng);
+        let mut tetris = Tetris {
+            settings,
+            seed,
+            board: vec![vec![Square::Empty; COLS]; ROWS],
+            current_block: Block::new(randomizer.next(&mut rng)),
+            rng,
+            randomizer,
+            hold: None,
+            can_hold: true,
+            gravity: 0,
+            shift: AutoShift::default(),
+            soft_drop: false,
+            lock_delay: LockDelay::default(),
+            points: 0,
+            lines: 0,
+            // The TGM level counter starts at 0
+            level: match settings.mode {
+                GameMode::Master => 0,
+                _ => 1,
+            },
+            back_to_back: false,
+            combo: None,
+            last_rotation: None,
+            label: None,
+            stats: Statistics::default(),
+            state: GameState::Menu,
+            events: Vec::new(),
+            frame: 0,
+            inputs: Vec::new(),
+            splits: Vec::new(),
+            garbage: Garbage::new(settings.garbage),
+            next_rise: rise_interval(0),
+            spawn_delay: 0,
+            master: Master::new(),
+        };
+        if settings.mode == GameMode::Dig {
+            tetris.raise_garbage(settings.dig_rows.clamp(1, MAX_DIG_ROWS));
+        }
+        tetris
+    }
+
+    pub fn step(&mut self, input: Input) {
+        if matches!(self.state, GameState::Playing | GameState::Pause) && input != Input::Tick {
+            self.inputs.push((self.frame, input.clone()));
+        }
+        match (self.state.clone(), input) {
+            (GameState::Menu, Input::Press(KeyEvent::Play)) => self.state = GameState::Playing,
+            (GameState::Playing, Input::Press(key)) => self.press(key),
+            (GameState::Playing, Input::Tick) => self.tick().unwrap_or_else(|()| self.game_over()),
+            (GameState::Playing | GameState::Pause, Input::Release(key)) => self.key_release(key),
+            (GameState::Pause, Input::Press(KeyEvent::Pause | KeyEvent::Play)) => {
+                self.state = GameState::Playing
+            }
+            (state, Input::Press(KeyEvent::Play)) if state.is_over() => {
+                *self = Tetris {
+                    state: GameState::Playing,
+                    ..Tetris::new(self.settings)
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn board(&self) -> &[Vec<Square>] {
+        &self.board
+    }
+
+    // Drains what happened since the last call
+    pub fn events(&mut self) -> impl Iterator<Item = Event> + '_ {
+        self.events.drain(..)
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    pub fn current_block(&self) -> &Block {
+        &self.current_block
+    }
+
+    pub fn hold(&self) -> Option<Piece> {
+        self.hold
+    }
+
+    pub fn preview(&self) -> impl Iterator<Item = &Piece> {
+        self.randomizer.preview()
+    }
+
+    pub fn points(&self) -> usize {
+        self.points
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    pub fn splits(&self) -> &[u64] {
+        &self.splits
+    }
+
+    // TGM grade earned so far in Master
+    pub fn grade(&self) -> &'static str {
+        self.master.grade(self.level, self.points)
+    }
+
+    // Counters shown under the hold piece, depending on the mode
+    fn side_panel(&self) -> Vec<String> {
+        let mut stats = match self.settings.mode {
+            GameMode::Endless => vec![
+                format!("Level: {}", self.level),
+                format!("Lines: {}", self.lines),
+                format!("Points: {}", self.points),
+            ],
+            GameMode::Sprint => vec![
+                format!("Time: {}", format_time(self.frame)),
+                format!(
+                    "Lines left: {}",
+                    self.line_goal()
+                        .unwrap_or_default()
+                        .saturating_sub(self.lines)
+                ),
+                format!("Points: {}", self.points),
+            ],
+            GameMode::Marathon => vec![
+                format!("Level: {}", self.level),
+                format!(
+                    "Lines: {}/{}",
+                    self.lines,
+                    self.line_goal().unwrap_or_default()
+                ),
+                format!("Points: {}", self.points),
+            ],
+            GameMode::Zen => vec![
+                format!("Speed: {}", self.settings.zen_speed),
+                format!("Lines: {}", self.lines),
+                format!("Points: {}", self.points),
+            ],
+            GameMode::Dig => vec![
+                format!("Time: {}", format_time(self.frame)),
+                format!("Garbage left: {}", self.garbage_left()),
+                format!("Lines: {}", self.lines),
+            ],
+            GameMode::Survival => vec![
+                format!("Time: {}", format_time(self.frame)),
+                format!(
+                    "Next row in: {}",
+                    format_time(self.next_rise.saturating_sub(self.frame))
+                ),
+                format!("Lines: {}", self.lines),
+                format!("Points: {}", self.points),
+            ],
+            GameMode::Master => vec![
+                format!(
+                    "Level: {}/{}",
+                    self.level,
+                    (self.level / 100 * 100 + 100).min(master::MAX_LEVEL)
+                ),
+                format!("Grade: {}", self.grade()),
+                format!("Time: {}", format_time(self.frame)),
+                format!("Points: {}", self.points),
+            ],
+            GameMode::Ultra => vec![
+                format!(
+                    "Time left: {}",
+                    format_time(
+                        self.settings
+                            .mode
+                            .time_limit()
+                            .unwrap_or_default()
+                            .saturating_sub(self.frame)
+                    )
+                ),
+                format!("Lines: {}", self.lines),
+                format!("Points: {}", self.points),
+            ],
+        };
+        if self.stats.perfect_clears > 0 {
+            stats.push(format!("Perfect clears: {}", self.stats.perfect_clears));
+        }
+        stats
+    }
+
+    // Everything needed to play the game again from the start
+    pub fn replay(&self) -> Replay {
+        Replay {
+            settings: Settings {
+                seed: Some(self.seed),
+                ..self.settings
+            },
+            inputs: self.inputs.clone(),
+            frames: self.frame,
+        }
+    }
+
+    fn press(&mut self, key: KeyEvent) {
+        let waiting = self.spawn_delay > 0;
+        let result = match key {
+            KeyEvent::Left | KeyEvent::Right => {
+                self.shift_press(key);
+                Ok(())
+            }
+            KeyEvent::Down => {
+                self.soft_drop = true;
+                if waiting {
+                    Ok(())
+                } else if self.settings.mode == GameMode::Master
+                    && !self.can_block_move(KeyEvent::Down)
+                {
+                    // A tap lands here too, since without key releases the release comes at once
+                    self.lock_block()
+                } else {
+                    self.block_down();
+                    Ok(())
+                }
+            }
+            // Only held keys carry over to the next piece
+            KeyEvent::HardDrop
+            | KeyEvent::RotateClockwise
+            | KeyEvent::RotateCounterClockwise
+            | KeyEvent::Rotate180
+            | KeyEvent::Hold
+                if waiting =>
+            {
+                Ok(())
+            }
+            KeyEvent::HardDrop => self.block_hard_drop(),
+            KeyEvent::RotateClockwise | KeyEvent::RotateCounterClockwise | KeyEvent::Rotate180 => {
+                self.block_rotate(key);
+                Ok(())
+            }
+            KeyEvent::Hold => self.block_hold(),
+            KeyEvent::Pause => {
+                self.state = GameState::Pause;
+                Ok(())
+            }
+            KeyEvent::Quit | KeyEvent::Play => Ok(()),
+        };
+        if result.is_err() {
+            self.game_over();
+        }
+    }
+
+    fn game_over(&mut self) {
+        self.state = GameState::EndScreen;
+        self.events.push(Event::GameOver);
+    }
+
+    // Ends the game without losing, as Won or Finished
+    fn finish(&mut self, state: GameState) {
+        self.state = state;
+        self.events.push(Event::Finished);
+    }
+
+    fn is_won(&self) -> bool {
+        match self.settings.mode {
+            GameMode::Dig => self.garbage_left() == 0,
+            GameMode::Master => self.level >= master::MAX_LEVEL,
+            _ => self.line_goal().is_some_and(|goal| self.lines >= goal),
+        }
+    }
+
+    fn garbage_left(&self) -> usize {
+        self.board
+            .iter()
+            .filter(|row| row.contains(&Square::Garbage))
+            .count()
+    }
+
+    // Pushes the stack up with rows of garbage from the bottom
+    fn raise_garbage(&mut self, rows: usize) {
+        self.board.drain(..rows);
+        for _ in 0..rows {
+            self.board.push(self.garbage.row(&mut self.rng));
+        }
+    }
+
+    // Raises garbage in Survival when it is due, lifting the falling piece out of the way
+    fn rise(&mut self) -> Result<(), ()> {
+        if self.settings.mode != GameMode::Survival || self.frame < self.next_rise {
+            return Ok(());
+        }
+        self.next_rise = self.frame + rise_interval(self.frame);
+        // Anything in the top row would be pushed off the board
+        if self.board[0].iter().any(|sq| *sq != Square::Empty) {
+            return Err(());
+        }
+        self.raise_garbage(1);
+        while self.is_collision(&self.current_block) {
+            self.current_block.up()?;
+        }
+        Ok(())
+    }
+
+    // The mode's line goal, Marathon's comes from the settings
+    fn line_goal(&self) -> Option<usize> {
+        match self.settings.mode {
+            GameMode::Marathon => Some(self.settings.marathon_lines),
+            mode => mode.line_goal(),
+        }
+    }
+
+    fn add_current_block(&mut self) {
+        for i in 0..4 {
+            self.board[self.current_block.position[i].row][self.current_block.position[i].col] =
+                Square::Occupied(self.current_block.color);
+        }
+    }
+
+    // Advances the game by one frame
+    fn tick(&mut self) -> Result<(), ()> {
+        self.frame += 1;
+        if let Some(limit) = self.settings.mode.time_limit() {
+            if self.frame >= limit {
+                self.finish(GameState::Finished);
+                return Ok(());
+            }
+        }
+        self.rise()?;
+        if let Some((_, frames)) = &mut self.label {
+            *frames -= 1;
+            if *frames == 0 {
+                self.label = None;
+            }
+        }
+        self.auto_shift();
+        if self.spawn_delay > 0 {
+            self.spawn_delay -= 1;
+            if self.spawn_delay == 0 {
+                return self.spawn_next();
+            }
+            return Ok(());
+        }
+        if self.can_block_move(KeyEvent::Down) {
+            self.lock_delay.frames = 0;
+            let speed = if self.soft_drop {
+                let sdf = self.settings.handling.sdf;
+                self.gravity_speed().saturating_mul(sdf).min(MAX_GRAVITY)
+            } else {
+                self.gravity_speed()
+            };
+            self.gravity += speed;
+            while self.gravity >= GRAVITY_UNIT && self.can_block_move(KeyEvent::Down) {
+                self.gravity -= GRAVITY_UNIT;
+                self.block_fall();
+                if self.soft_drop {
+                    self.drop_points(1, 1);
+                }
+            }
+            if !self.can_block_move(KeyEvent::Down) {
+                self.gravity = 0;
+            }
+            return Ok(());
+        }
+        self.lock_delay.frames += 1;
+        // Holding soft drop on the ground locks at once in Master, as in TGM
+        let soft_lock = self.soft_drop && self.settings.mode == GameMode::Master;
+        if self.lock_delay.frames >= self.lock_delay_frames()
+            || self.lock_delay.resets >= LOCK_RESETS
+            || soft_lock
+        {
+            return self.lock_block();
+        }
+        Ok(())
+    }
+
+    fn lock_block(&mut self) -> Result<(), ()> {
+        self.add_current_block();
+        let spin = self.t_spin();
+        let level = self.level;
+        let lines = self.remove_lines_completed();
+        self.score_lines(lines, spin, level);
+        if self.settings.mode == GameMode::Master {
+            self.master
+                .check(level, self.level, self.points, self.frame);
+        }
+        if self.is_won() {
+            self.finish(GameState::Won);
+            return Ok(());
+        }
+        self.spawn_delay = self.spawn_delay_frames(lines);
+        if self.spawn_delay > 0 {
+            return Ok(());
+        }
+        self.spawn_next()
+    }
+
+    fn spawn_next(&mut self) -> Result<(), ()> {
+        let next_block = Block::new(self.randomizer.next(&mut self.rng));
+        if self.is_end() || self.is_collision(&next_block) {
+            self.top_out()?;
+        }
+        self.spawn_block(next_block);
+        self.can_hold = true;
+        if self.settings.mode == GameMode::Master {
+            self.level = master::piece_level(self.level);
+        }
+        // At 20G a new piece lands as soon as it spawns
+        if self.gravity_speed() >= MAX_GRAVITY {
+            self.current_block = self.ghost_block();
+        }
+        Ok(())
+    }
+
+    // ARE, and the line clear delay on top after clearing lines
+    fn spawn_delay_frames(&self, lines: usize) -> u32 {
+        if self.settings.mode != GameMode::Master {
+            return 0;
+        }
+        let timing = master::timing(self.level);
+        match lines {
+            0 => timing.are,
+            _ => timing.clear + timing.line_are,
+        }
+    }
+
+    fn lock_delay_frames(&self) -> u32 {
+        match self.settings.mode {
+            GameMode::Master => master::timing(self.level).lock,
+            _ => LOCK_DELAY_FRAMES,
+        }
+    }
+
+    // Ends the game, except in modes that clear the bottom of the stack away and carry on
+    fn top_out(&mut self) -> Result<(), ()> {
+        if self.settings.mode.can_top_out() {
+            return Err(());
+        }
+        self.board.truncate(ROWS - ZEN_CLEARED_ROWS);
+        self.board
+            .splice(0..0, vec![vec![Square::Empty; COLS]; ZEN_CLEARED_ROWS]);
+        Ok(())
+    }
+
+    // Guideline curve: a row falls every (0.8 - (level - 1) * 0.007)^(level - 1) seconds
+    fn gravity_speed(&self) -> u32 {
+        let level = match self.settings.mode {
+            GameMode::Zen => self.settings.zen_speed,
+            GameMode::Master => return MAX_GRAVITY,
+            _ => self.level,
+        };
+        let level = level.clamp(1, MAX_ZEN_SPEED) as i32 - 1;
+        let seconds = (0.8 - level as f64 * 0.007).powi(level);
+        let speed = (GRAVITY_UNIT as f64 / (seconds * FPS as f64)).ceil() as u32;
+        speed.min(MAX_GRAVITY)
+    }
+
+    fn spawn_block(&mut self, block: Block) {
+        self.current_block = block;
+        self.gravity = 0;
+        self.lock_delay = LockDelay::default();
+        self.last_rotation = None;
+    }
+
+    fn block_fall(&mut self) {
+        self.current_block.down();
+        self.last_rotation = None;
+        let bottom = self
+            .current_block
+            .position
+            .iter()
+            .map(|coor| coor.row)
+            .max();
+        if bottom > Some(self.lock_delay.lowest_row) {
+            self.lock_delay.lowest_row = bottom.unwrap();
+            self.lock_delay.resets = 0;
+        }
+    }
+
+    // A move or rotation on the ground restarts the lock delay, a limited number of times
+    fn reset_lock_delay(&mut self) {
+        if self.lock_delay.frames > 0 && self.lock_delay.resets < LOCK_RESETS {
+            self.lock_delay.frames = 0;
+            self.lock_delay.resets += 1;
+        }
+    }
+
+    fn block_down(&mut self) {
+        if self.can_block_move(KeyEvent::Down) {
+            self.block_fall();
+            self.drop_points(1, 1);
+        }
+    }
+
+    // Master counts dropped rows into the points of the lock instead
+    fn drop_points(&mut self, rows: usize, per_row: usize) {
+        match self.settings.mode {
+            GameMode::Master => self.master.drop(rows),
+            _ => self.points += rows * per_row,
+        }
+    }
+
+    fn block_hard_drop(&mut self) -> Result<(), ()> {
+        let ghost = self.ghost_block();
+        if ghost != self.current_block {
+            self.drop_points(
+                ghost.position[0].row - self.current_block.position[0].row,
+                2,
+            );
+            self.current_block = ghost;
+            self.last_rotation = None;
+        }
+        self.lock_block()
+    }
+
+    pub fn ghost_block(&self) -> Block {
+        let mut ghost = self.current_block;
+        loop {
+            let mut block = ghost;
+            block.down();
+            if self.is_collision(&block) {
+                return ghost;
+            }
+            ghost = block;
+        }
+    }
+
+    fn shift_press(&mut self, key: KeyEvent) {
+        match key {
+            KeyEvent::Left => self.shift.left = true,
+            KeyEvent::Right => self.shift.right = true,
+            _ => return,
+        }
+        self.shift.direction = Some(key.clone());
+        self.shift.frames = 0;
+        if self.spawn_delay == 0 {
+            self.block_shift(&key);
+        }
+    }
+
+    fn key_release(&mut self, key: KeyEvent) {
+        match key {
+            KeyEvent::Left => self.shift.left = false,
+            KeyEvent::Right => self.shift.right = false,
+            KeyEvent::Down => self.soft_drop = false,
+            _ => return,
+        }
+        // Releasing the active direction hands over to the other one if it is still held
+        if self.shift.direction == Some(key) {
+            self.shift.frames = 0;
+            self.shift.direction = match (self.shift.left, self.shift.right) {
+                (true, _) => Some(KeyEvent::Left),
+                (_, true) => Some(KeyEvent::Right),
+                _ => None,
+            };
+        }
+    }
+
+    fn auto_shift(&mut self) {
+        let Some(direction) = self.shift.direction.clone() else {
+            return;
+        };
+        let Handling { das, arr, .. } = self.settings.handling;
+        self.shift.frames += 1;
+        // Auto shift charges between pieces, the next piece moves as soon as it spawns
+        if self.shift.frames < das || self.spawn_delay > 0 {
+            return;
+        }
+        if arr == 0 {
+            while self.can_block_move(direction.clone()) {
+                self.block_shift(&direction);
+            }
+        } else if (self.shift.frames - das).is_multiple_of(arr) {
+            self.block_shift(&direction);
+        }
+    }
+
+    fn block_shift(&mut self, direction: &KeyEvent) {
+        match direction {
+            KeyEvent::Left => self.block_left(),
+            KeyEvent::Right => self.block_right(),
+            _ => (),
+        }
+    }
+
+    fn block_left(&mut self) {
+        if self.can_block_move(KeyEvent::Left) {
+            self.current_block.left();
+            self.last_rotation = None;
+            self.reset_lock_delay();
+        }
+    }
+
+    fn block_right(&mut self) {
+        if self.can_block_move(KeyEvent::Right) {
+            self.current_block.right();
+            self.last_rotation = None;
+            self.reset_lock_delay();
+        }
+    }
+
+    fn block_rotate(&mut self, rotation: KeyEvent) {
+        if let Some((kick, block)) = self
+            .current_block
+            .rotate(rotation.clone())
+            .into_iter()
+            .find(|(_, block)| !self.is_collision(block))
+        {
+            self.current_block = block;
+            self.last_rotation = Some((rotation, kick));
+            self.reset_lock_delay();
+        }
+    }
+
+    // 3-corner rule: a T that last rotated into place with three diagonal corners blocked
+    fn t_spin(&self) -> Spin {
+        let Some((rotation, kick)) = &self.last_rotation else {
+            return Spin::None;
+        };
+        if self.current_block.piece != Piece::T {
+            return Spin::None;
+        }
+        let center = self.current_block.position[0];
+        let corners = [(-1, -1), (-1, 1), (1, 1), (1, -1)].map(|(row, col)| {
+            center.offset(row, col).map_or(true, |coor| {
+                coor.col >= COLS || coor.row >= ROWS || self.is_occupied(coor)
+            })
+        });
+        if corners.iter().filter(|&&corner| corner).count() < 3 {
+            return Spin::None;
+        }
+        let front = self.current_block.rotation_pos;
+        // The last kick of a quarter turn always counts as a full T-spin
+        let last_kick = *rotation != KeyEvent::Rotate180 && *kick == 4;
+        if (corners[front] && corners[(front + 1) % 4]) || last_kick {
+            Spin::Full
+        } else {
+            Spin::Mini
+        }
+    }
+
+    fn block_hold(&mut self) -> Result<(), ()> {
+        if !self.can_hold {
+            return Ok(());
+        }
+        let held = self.current_block.piece;
+        let piece = match self.hold.replace(held) {
+            Some(piece) => piece,
+            None => self.randomizer.next(&mut self.rng),
+        };
+        self.spawn_block(Block::new(piece));
+        self.can_hold = false;
+        self.events.push(Event::Hold(held));
+        if self.is_collision(&self.current_block) {
+            return self.top_out();
+        }
+        Ok(())
+    }
+
+    fn can_block_move(&self, movement: KeyEvent) -> bool {
+        self.current_block
+            .position
+            .into_iter()
+            .map(|sq| match movement {
+                KeyEvent::Down => Ok(sq.down(1)),
+                KeyEvent::Right => Ok(sq.right(1)),
+                KeyEvent::Left => Ok(sq.left(1)?),
+                _ => Err(()),
+            })
+            .all(|sq| {
+                sq.is_ok()
+                    && sq.unwrap().col < COLS
+                    && sq.unwrap().row < ROWS
+                    && !self.is_occupied(sq.unwrap())
+            })
+    }
+
+    fn is_occupied(&self, coor: Coordinates) -> bool {
+        self.board[coor.row][coor.col] != Square::Empty
+    }
+
+    fn is_collision(&self, block: &Block) -> bool {
+        block
+            .position
+            .into_iter()
+            .any(|sq| sq.col >= COLS || sq.row >= ROWS || self.is_occupied(sq))
+    }
+
+    fn remove_lines_completed(&mut self) -> usize {
+        self.board.retain(|val| val.contains(&Square::Empty));
+        let deleted = ROWS - self.board.len();
+        if deleted > 0 {
+            self.board
+                .splice(0..0, vec![vec![Square::Empty; COLS]; deleted]);
+            self.lines += deleted;
+            self.level = match self.settings.mode {
+                GameMode::Master => (self.level + deleted).min(master::MAX_LEVEL),
+                _ => self.lines / LINES_PER_LEVEL + 1,
+            };
+            while self.splits.len() < self.lines / SPLIT_LINES {
+                self.splits.push(self.frame);
+            }
+        }
+        deleted
+    }
+
+    fn score_lines(&mut self, lines: usize, spin: Spin, level: usize) {
+        let mut clear = LineClear {
+            lines,
+            spin,
+            ..Default::default()
+        };
+        if lines == 0 {
+            self.combo = None;
+        } else {
+            clear.combo = self.combo.map_or(0, |combo| combo + 1);
+            clear.perfect = self.board.iter().flatten().all(|sq| *sq == Square::Empty);
+            clear.back_to_back = self.back_to_back && clear.is_difficult();
+            self.back_to_back = clear.is_difficult();
+            self.combo = Some(clear.combo);
+        }
+        if self.settings.mode == GameMode::Master {
+            self.points += self.master.points(level, lines, clear.perfect);
+        } else if lines > 0 || spin != Spin::None {
+            self.points += clear.points(level);
+        }
+        if lines > 0 || spin != Spin::None {
+            self.stats.record(&clear);
+            self.label = Some((clear.label(), LABEL_FRAMES));
+        }
+        self.events.push(Event::Lock(clear));
+    }
+
+    fn is_end(&self) -> bool {
+        self.board
+            .iter()
+            .rev()
+            .skip(ROWS - 2)
+            .any(|val| val.iter().any(|sq| *sq != Square::Empty))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_block(col: usize, row: usize) -> Block {
+        Block {
+            position: [
+                Coordinates { row, col },
+                Coordinates { row, col },
+                Coordinates { row, col },
+                Coordinates { row, col },
+            ],
+            color: Color::Red,
+            piece: Piece::I,
+            rotation_pos: 1,
+        }
+    }
+
+    fn create_tetris(col: usize, row: usize) -> Tetris {
+        let block = create_block(col, row);
+
+        let board = vec![
+            vec![
+                Square::Empty,
+                Square::Empty,
+                Square::Empty,
+                Square::Occupied(Color::Blue),
+                Square::Occupied(Color::Blue),
+                Square::Occupied(Color::Blue),
+                Square::Occupied(Color::Blue),
+                Square::Empty,
+                Square::Empty,
+                Square::Empty
+            ];
+            ROWS
+        ];
+
+        Tetris {
+            settings: Settings::default(),
+            seed: 0,
+            rng: ChaCha8Rng::seed_from_u64(0),
+            board,
+            current_block: block,
+            randomizer: Randomizer::new(RandomizerKind::Bag, 1, &mut ChaCha8Rng::seed_from_u64(0)),
+            hold: None,
+            can_hold: true,
+            gravity: 0,
+            shift: AutoShift::default(),
+            soft_drop: false,
+            lock_delay: LockDelay::default(),
+            points: 1,
+            lines: 0,
+            level: 1,
+            back_to_back: false,
+            combo: None,
+            last_rotation: None,
+            label: None,
+            stats: Statistics::default(),
+            state: GameState::Playing,
+            events: Vec::new(),
+            frame: 0,
+            inputs: Vec::new(),
+            splits: Vec::new(),
+            garbage: Garbage::new(GarbageKind::Messy),
+            next_rise: rise_interval(0),
+            spawn_delay: 0,
+            master: Master::new(),
+        }
+    }
+
+    // A started game where hard dropping the I piece clears the bottom row
+    fn create_single_clear(settings: Settings) -> Tetris {
+        let mut tetris = Tetris::new(settings);
+        tetris.step(Input::Press(KeyEvent::Play));
+        tetris.current_block = Block::new(Piece::I);
+        let ghost = tetris.ghost_block();
+        for col in 0..COLS {
+            if !ghost.position.contains(&Coordinates::new(ROWS - 1, col)) {
+                tetris.board[ROWS - 1][col] = Square::Occupied(Color::Blue);
+            }
+        }
+        tetris
+    }
+
+    #[test]
+    fn test_add_block() {
+        let mut tetris = create_tetris(8, 8);
+        assert!(!tetris.is_occupied(Coordinates::new(8, 8)));
+        tetris.add_current_block();
+        assert!(tetris.is_occupied(Coordinates::new(8, 8)));
+    }
+
+    #[test]
+    fn test_collision() {
+        let tetris = create_tetris(8, 8);
+        assert!(tetris.is_collision(&create_block(3, 5)));
+        assert!(!tetris.is_collision(&create_block(2, 5)));
+        assert!(tetris.is_collision(&create_block(10, 5)));
+    }
+
+    #[test]
+    fn test_rotate_wall_kick() {
+        let mut tetris = create_tetris(8, 8);
+        tetris.board = vec![vec![Square::Empty; COLS]; ROWS];
+        tetris.current_block = Block {
+            position: get_piece_position(Piece::J, 1, Coordinates::new(10, 0)).unwrap(),
+            color: Color::Red,
+            piece: Piece::J,
+            rotation_pos: 1,
+        };
+        tetris.block_rotate(KeyEvent::RotateClockwise);
+        assert_eq!(tetris.current_block.rotation_pos, 2);
+        assert_eq!(tetris.current_block.position[0], Coordinates::new(10, 1));
+    }
+
+    #[test]
+    fn test_rotate_i_true_rotation() {
+        let block = Block {
+            position: get_piece_position(Piece::I, 0, Coordinates::new(10, 4)).unwrap(),
+            color: Color::Red,
+            piece: Piece::I,
+            rotation_pos: 0,
+        };
+        let mut rotated = block.rotate(KeyEvent::RotateClockwise)[0].1.position;
+        rotated.sort_by_key(|coor| coor.row);
+        assert_eq!(rotated, [9, 10, 11, 12].map(|row| Coordinates::new(row, 5)));
+    }
+
+    #[test]
+    fn test_rotate_counter_clockwise_and_180() {
+        let block = Block {
+            position: get_piece_position(Piece::T, 0, Coordinates::new(10, 4)).unwrap(),
+            color: Color::Red,
+            piece: Piece::T,
+            rotation_pos: 0,
+        };
+        let counter = block.rotate(KeyEvent::RotateCounterClockwise);
+        assert_eq!(counter[0].1.rotation_pos, 3);
+        assert_eq!(counter[1].1.position[0], Coordinates::new(10, 5));
+        let half = block.rotate(KeyEvent::Rotate180);
+        assert_eq!(half.len(), 6);
+        assert_eq!(half[0].1.rotation_pos, 2);
+        assert_eq!(half[1].1.position[0], Coordinates::new(9, 4));
+    }
+
+    #[test]
+    fn test_hold_once_per_drop() {
+        let mut tetris = Tetris::new(Settings::default());
+        let first = tetris.current_block.piece;
+        let second = *tetris.randomizer.preview().next().unwrap();
+        assert_eq!(tetris.block_hold(), Ok(()));
+        assert_eq!(tetris.hold, Some(first));
+        assert_eq!(tetris.current_block.piece, second);
+        assert_eq!(tetris.block_hold(), Ok(()));
+        assert_eq!(tetris.current_block.piece, second);
+        tetris.can_hold = true;
+        assert_eq!(tetris.block_hold(), Ok(()));
+        assert_eq!(tetris.hold, Some(second));
+        assert_eq!(tetris.current_block.piece, first);
+    }
+
+    #[test]
+    fn test_hard_drop() {
+        let mut tetris = Tetris::new(Settings::default());
+        let ghost = tetris.ghost_block();
+        assert!(ghost.position.iter().any(|coor| coor.row == ROWS - 1));
+        assert_eq!(tetris.block_hard_drop(), Ok(()));
+        assert!(ghost.position.iter().all(|&coor| tetris.is_occupied(coor)));
+        assert!(tetris.can_hold);
+    }
+
+    #[test]
+    fn test_lock_delay() {
+        let mut tetris = Tetris::new(Settings::default());
+        tetris.current_block = Block::new(Piece::T);
+        tetris.current_block = tetris.ghost_block();
+        for _ in 1..LOCK_DELAY_FRAMES {
+            assert_eq!(tetris.tick(), Ok(()));
+        }
+        tetris.block_left();
+        assert_eq!(tetris.tick(), Ok(()));
+        tetris.block_right();
+        assert_eq!(tetris.lock_delay.resets, 2);
+        for _ in 1..LOCK_DELAY_FRAMES {
+            assert_eq!(tetris.tick(), Ok(()));
+        }
+        assert!(tetris.board.iter().flatten().all(|sq| *sq == Square::Empty));
+        assert_eq!(tetris.tick(), Ok(()));
+        assert!(tetris.board.iter().flatten().any(|sq| *sq != Square::Empty));
+    }
+
+    #[test]
+    fn test_gravity_curve() {
+        let mut tetris = Tetris::new(Settings::default());
+        assert_eq!(GRAVITY_UNIT.div_ceil(tetris.gravity_speed()), 60);
+        tetris.level = 10;
+        assert_eq!(GRAVITY_UNIT.div_ceil(tetris.gravity_speed()), 4);
+        tetris.level = 20;
+        assert_eq!(tetris.gravity_speed(), MAX_GRAVITY);
+        tetris.lines = 9;
+        tetris.level = 1;
+        tetris.board[ROWS - 1] = vec![Square::Occupied(Color::Red); COLS];
+        tetris.remove_lines_completed();
+        assert_eq!(tetris.level, 2);
+    }
+
+    #[test]
+    fn test_score_back_to_back_and_combo() {
+        let mut tetris = Tetris::new(Settings::default());
+        tetris.board[ROWS - 1][0] = Square::Occupied(Color::Blue);
+        tetris.score_lines(4, Spin::None, 1);
+        tetris.score_lines(4, Spin::None, 1);
+        assert_eq!(tetris.points, 800 + 1200 + 50);
+        tetris.score_lines(1, Spin::None, 1);
+        tetris.score_lines(0, Spin::None, 1);
+        assert_eq!(tetris.points, 2050 + 200);
+        assert!(!tetris.back_to_back);
+        assert_eq!(tetris.combo, None);
+    }
+
+    #[test]
+    fn test_t_spin_double() {
+        let mut tetris = Tetris::new(Settings::default());
+        let block = Square::Occupied(Color::Blue);
+        tetris.board[ROWS - 1] = vec![block; COLS];
+        tetris.board[ROWS - 1][4] = Square::Empty;
+        tetris.board[ROWS - 2] = vec![block; COLS];
+        for col in 3..6 {
+            tetris.board[ROWS - 2][col] = Square::Empty;
+        }
+        tetris.board[ROWS - 3][3] = block;
+        tetris.current_block = Block {
+            position: get_piece_position(Piece::T, 2, Coordinates::new(ROWS - 2, 4)).unwrap(),
+            color: Color::Violet,
+            piece: Piece::T,
+            rotation_pos: 2,
+        };
+        assert_eq!(tetris.t_spin(), Spin::None);
+        tetris.last_rotation = Some((KeyEvent::RotateClockwise, 0));
+        assert_eq!(tetris.t_spin(), Spin::Full);
+        assert_eq!(tetris.lock_block(), Ok(()));
+        assert_eq!(tetris.points, 1200);
+        assert!(tetris.back_to_back);
+    }
+
+    #[test]
+    fn test_perfect_clear() {
+        let mut tetris = Tetris::new(Settings::default());
+        tetris.board[ROWS - 1] = vec![Square::Occupied(Color::Blue); COLS];
+        for col in 3..7 {
+            tetris.board[ROWS - 1][col] = Square::Empty;
+        }
+        tetris.current_block = Block::new(Piece::I);
+        assert_eq!(tetris.block_hard_drop(), Ok(()));
+        assert_eq!(tetris.stats.perfect_clears, 1);
+        assert_eq!(tetris.points, 2 * (ROWS - 5) + 100 + 800);
+    }
+
+    #[test]
+    fn test_auto_shift() {
+        let mut tetris = Tetris::new(Settings::default());
+        tetris.current_block = Block::new(Piece::T);
+        let col = |tetris: &Tetris| tetris.current_block.position[0].col;
+        tetris.shift_press(KeyEvent::Left);
+        assert_eq!(col(&tetris), 3);
+        for _ in 1..10 {
+            tetris.tick().unwrap();
+        }
+        assert_eq!(col(&tetris), 3);
+        tetris.tick().unwrap();
+        assert_eq!(col(&tetris), 2);
+        tetris.shift_press(KeyEvent::Right);
+        assert_eq!(col(&tetris), 3);
+        tetris.key_release(KeyEvent::Right);
+        assert_eq!(tetris.shift.direction, Some(KeyEvent::Left));
+        tetris.settings.handling.arr = 0;
+        for _ in 0..10 {
+            tetris.tick().unwrap();
+        }
+        assert_eq!(col(&tetris), 1);
+    }
+
+    #[test]
+    fn test_step_and_events() {
+        let mut tetris = Tetris::new(Settings::default());
+        tetris.step(Input::Tick);
+        assert_eq!(*tetris.state(), GameState::Menu);
+        tetris.step(Input::Press(KeyEvent::Play));
+        tetris.step(Input::Press(KeyEvent::HardDrop));
+        let piece = tetris.current_block().piece;
+        tetris.step(Input::Press(KeyEvent::Hold));
+        assert_eq!(
+            tetris.events().collect::<Vec<Event>>(),
+            [Event::Lock(LineClear::default()), Event::Hold(piece)]
+        );
+        assert_eq!(tetris.events().count(), 0);
+        assert_eq!(
+            tetris
+                .board()
+                .iter()
+                .flatten()
+                .filter(|sq| **sq != Square::Empty)
+                .count(),
+            4
+        );
+        tetris.step(Input::Press(KeyEvent::Pause));
+        let block = *tetris.current_block();
+        for _ in 0..FPS {
+            tetris.step(Input::Tick);
+        }
+        assert_eq!(*tetris.current_block(), block);
+        tetris.step(Input::Press(KeyEvent::Play));
+        assert_eq!(*tetris.state(), GameState::Playing);
+    }
+
+    #[test]
+    fn test_sprint_finishes_at_forty_lines() {
+        let mut tetris = create_single_clear(Settings {
+            mode: GameMode::Sprint,
+            ..Default::default()
+        });
+        tetris.lines = 39;
+        tetris.splits = vec![600, 1200, 1800];
+        tetris.frame = 2500;
+        assert!(tetris.side_panel().contains(&String::from("Lines left: 1")));
+        tetris.step(Input::Press(KeyEvent::HardDrop));
+        assert_eq!(*tetris.state(), GameState::Won);
+        assert_eq!(tetris.events().last(), Some(Event::Finished));
+        assert_eq!(tetris.splits(), [600, 1200, 1800, 2500]);
+        tetris.step(Input::Tick);
+        assert_eq!(tetris.frame(), 2500);
+        tetris.step(Input::Press(KeyEvent::Play));
+        assert_eq!(*tetris.state(), GameState::Playing);
+        assert_eq!(tetris.lines(), 0);
+    }
+
+    #[test]
+    fn test_marathon_is_won_at_the_line_goal() {
+        let mut tetris = create_single_clear(Settings {
+            mode: GameMode::Marathon,
+            marathon_lines: 20,
+            ..Default::default()
+        });
+        tetris.lines = 19;
+        assert!(tetris.side_panel().contains(&String::from("Lines: 19/20")));
+        tetris.step(Input::Press(KeyEvent::HardDrop));
+        assert_eq!(*tetris.state(), GameState::Won);
+        assert!(tetris.state().is_over());
+        assert!(tetris.to_string().contains("YOU WON!"));
+    }
+
+    #[test]
+    fn test_zen_clears_the_bottom_instead_of_topping_out() {
+        for (mode, state) in [
+            (GameMode::Endless, GameState::EndScreen),
+            (GameMode::Zen, GameState::Playing),
+        ] {
+            let mut tetris = Tetris::new(Settings {
+                mode,
+                zen_speed: 5,
+                ..Default::default()
+            });
+            tetris.step(Input::Press(KeyEvent::Play));
+            for row in tetris.board.iter_mut().skip(1) {
+                *row = vec![Square::Occupied(Color::Blue); COLS];
+                row[0] = Square::Empty;
+            }
+            tetris.step(Input::Press(KeyEvent::HardDrop));
+            assert_eq!(*tetris.state(), state);
+        }
+        let mut tetris = Tetris::new(Settings {
+            mode: GameMode::Zen,
+            ..Default::default()
+        });
+        tetris.level = 15;
+        assert_eq!(tetris.gravity_speed(), 1093);
+        tetris.settings.zen_speed = 5;
+        tetris.board[ROWS - 1][0] = Square::Occupied(Color::Red);
+        assert_eq!(tetris.top_out(), Ok(()));
+        assert!(tetris.board[..ZEN_CLEARED_ROWS]
+            .iter()
+            .flatten()
+            .all(|sq| *sq == Square::Empty));
+        assert_eq!(tetris.board[ROWS - 1][0], Square::Empty);
+        assert!(tetris.gravity_speed() > 1093);
+    }
+
+    #[test]
+    fn test_dig_is_won_when_the_garbage_is_gone() {
+        let settings = Settings {
+            mode: GameMode::Dig,
+            seed: Some(5),
+            dig_rows: 4,
+            ..Default::default()
+        };
+        let tetris = Tetris::new(settings);
+        assert_eq!(tetris.garbage_left(), 4);
+        assert_eq!(tetris.board(), Tetris::new(settings).board());
+        assert!(tetris.board()[ROWS - 5]
+            .iter()
+            .all(|sq| *sq == Square::Empty));
+
+        // One row of garbage with the I piece's four columns open
+        let mut tetris = create_single_clear(Settings::default());
+        tetris.settings = settings;
+        for sq in tetris.board[ROWS - 1].iter_mut() {
+            if *sq != Square::Empty {
+                *sq = Square::Garbage;
+            }
+        }
+        assert!(tetris
+            .side_panel()
+            .contains(&String::from("Garbage left: 1")));
+        tetris.step(Input::Press(KeyEvent::HardDrop));
+        assert_eq!(*tetris.state(), GameState::Won);
+    }
+
+    #[test]
+    fn test_survival_lifts_the_piece_over_rising_garbage() {
+        let mut tetris = Tetris::new(Settings {
+            mode: GameMode::Survival,
+            ..Default::default()
+        });
+        tetris.step(Input::Press(KeyEvent::Play));
+        tetris.current_block = tetris.ghost_block();
+        let block = tetris.current_block;
+        tetris.frame = tetris.next_rise - 1;
+        tetris.step(Input::Tick);
+        assert_eq!(*tetris.state(), GameState::Playing);
+        assert!(tetris.board[ROWS - 1].contains(&Square::Garbage));
+        assert!(!tetris.is_collision(&tetris.current_block));
+        for (lifted, pos) in tetris.current_block.position.iter().zip(block.position) {
+            assert_eq!(lifted.row + 1, pos.row);
+        }
+        assert_eq!(tetris.next_rise, tetris.frame + 10 * FPS as u64);
+        assert_eq!(rise_interval(95 * FPS as u64), 7 * FPS as u64);
+        assert_eq!(rise_interval(3600 * FPS as u64), 2 * FPS as u64);
+
+        tetris.board[0][0] = Square::Garbage;
+        tetris.frame = tetris.next_rise - 1;
+        tetris.step(Input::Tick);
+        assert_eq!(*tetris.state(), GameState::EndScreen);
+    }
+
+    #[test]
+    fn test_master_delays_and_levels() {
+        let mut tetris = Tetris::new(Settings {
+            mode: GameMode::Master,
+            ..Default::default()
+        });
+        tetris.step(Input::Press(KeyEvent::Play));
+        tetris.step(Input::Tick);
+        assert_eq!(tetris.current_block, tetris.ghost_block());
+        assert_eq!(tetris.level(), 0);
+        tetris.step(Input::Press(KeyEvent::HardDrop));
+        for _ in 1..18 {
+            tetris.step(Input::Press(KeyEvent::RotateClockwise));
+            tetris.step(Input::Tick);
+        }
+        assert_eq!(tetris.level(), 0);
+        tetris.step(Input::Tick);
+        assert_eq!(tetris.level(), 1);
+        assert_eq!(tetris.current_block, tetris.ghost_block());
+        assert_eq!(tetris.current_block.rotation_pos, 0);
+
+        // Soft drop on the ground locks even when released before the next frame
+        tetris.step(Input::Press(KeyEvent::Down));
+        tetris.step(Input::Release(KeyEvent::Down));
+        assert_eq!(tetris.spawn_delay, 18);
+
+        let mut tetris = create_single_clear(Settings {
+            mode: GameMode::Master,
+            ..Default::default()
+        });
+        tetris.level = 98;
+        tetris.step(Input::Press(KeyEvent::HardDrop));
+        assert_eq!(tetris.level(), 99);
+        // (ceil((98 + 1) / 4) + 18 rows dropped) times 4 for clearing the board
+        assert_eq!(tetris.points(), (25 + 18) * 4);
+        assert_eq!(tetris.spawn_delay, 12 + 14);
+        for _ in 0..26 {
+            tetris.step(Input::Tick);
+        }
+        assert_eq!(tetris.level(), 99);
+        assert!(tetris.side_panel().contains(&String::from("Level: 99/100")));
+        assert_eq!(tetris.grade(), "9");
+    }
+
+    #[test]
+    fn test_ultra_ends_after_two_minutes() {
+        let mut tetris = Tetris::new(Settings {
+            mode: GameMode::Ultra,
+            ..Default::default()
+        });
+        tetris.step(Input::Press(KeyEvent::Play));
+        tetris.frame = 120 * FPS as u64 - 2;
+        tetris.step(Input::Tick);
+        assert_eq!(*tetris.state(), GameState::Playing);
+        assert!(tetris
+            .side_panel()
+            .contains(&String::from("Time left: 0:00.016")));
+        tetris.step(Input::Tick);
+        assert_eq!(*tetris.state(), GameState::Finished);
+        assert_eq!(tetris.events().last(), Some(Event::Finished));
+        assert!(tetris.to_string().contains("TIME'S UP!"));
+        assert!(tetris.to_string().contains("Tetrises: 0"));
+    }
+}
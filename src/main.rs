@@ -1,39 +1,198 @@
 use std::{
-    fmt,
-    fmt::Display,
-    io::stdout,
+    fs,
+    io::{stdout, ErrorKind, Write},
     sync::{mpsc, Arc, Mutex},
     thread,
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
 use crossterm::{
-    cursor, execute, style,
-    terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
+    cursor,
+    event::{
+        self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEventKind,
+        KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags,
+    },
+    execute, style,
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, Clear, ClearType,
+    },
 };
-use rand::{distributions::Standard, prelude::Distribution, Rng};
+use keymap::{Action, Keymap};
+use scores::{HighScores, Score};
+use tetris::{
+    GameMode, GameState, GarbageKind, Input, KeyEvent, Playback, RandomizerKind, RankBy, Replay,
+    Settings, Tetris, GAME_MODES,
+};
+
+mod config;
+mod keymap;
+mod scores;
 
 struct CleanUp;
 
 impl Drop for CleanUp {
     fn drop(&mut self) {
+        execute!(stdout(), PopKeyboardEnhancementFlags, DisableBracketedPaste)
+            .expect("Unable to restore keyboard");
         disable_raw_mode().expect("Unable to disable raw mode")
     }
 }
 
+struct Options {
+    settings: Settings,
+    replay: Option<Replay>,
+}
+
+fn parse_args(settings: Settings) -> Result<Options, String> {
+    let mut options = Options {
+        settings,
+        replay: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--randomizer" => {
+                let name = args.next().unwrap_or_default();
+                options.settings.randomizer = RandomizerKind::from_name(&name).ok_or(format!(
+                    "unknown randomizer '{}', use bag, classic or tgm",
+                    name
+                ))?;
+            }
+            "--seed" => {
+                options.settings.seed = Some(
+                    args.next()
+                        .and_then(|seed| seed.parse().ok())
+                        .ok_or("--seed needs a number between 0 and 18446744073709551615")?,
+                );
+            }
+            "--das" | "--arr" | "--sdf" => {
+                let frames = args
+                    .next()
+                    .and_then(|frames| frames.parse().ok())
+                    .ok_or(format!("{} needs a number of frames", arg))?;
+                match arg.as_str() {
+                    "--das" => options.settings.handling.das = frames,
+                    "--arr" => options.settings.handling.arr = frames,
+                    _ if frames < 1 => {
+                        return Err(String::from("--sdf needs a factor of at least 1"))
+                    }
+                    _ => options.settings.handling.sdf = frames,
+                }
+            }
+            "--lines" => {
+                options.settings.marathon_lines = args
+                    .next()
+                    .and_then(|lines| lines.parse().ok())
+                    .filter(|lines| *lines > 0)
+                    .ok_or("--lines needs a number of lines")?;
+            }
+            "--zen-speed" => {
+                options.settings.zen_speed = args
+                    .next()
+                    .and_then(|speed| speed.parse().ok())
+                    .filter(|speed| (1..=tetris::MAX_ZEN_SPEED).contains(speed))
+                    .ok_or(format!(
+                        "zen speed must be between 1 and {}",
+                        tetris::MAX_ZEN_SPEED
+                    ))?;
+            }
+            "--garbage" => {
+                let name = args.next().unwrap_or_default();
+                options.settings.garbage = GarbageKind::from_name(&name)
+                    .ok_or(format!("unknown garbage '{}', use clean or messy", name))?;
+            }
+            "--dig-rows" => {
+                options.settings.dig_rows = args
+                    .next()
+                    .and_then(|rows| rows.parse().ok())
+                    .filter(|rows| (1..=tetris::MAX_DIG_ROWS).contains(rows))
+                    .ok_or(format!(
+                        "dig rows must be between 1 and {}",
+                        tetris::MAX_DIG_ROWS
+                    ))?;
+            }
+            "--previews" => {
+                options.settings.previews = args
+                    .next()
+                    .and_then(|previews| previews.parse().ok())
+                    .filter(|previews| (1..=tetris::MAX_PREVIEWS).contains(previews))
+                    .ok_or(format!(
+                        "previews must be between 1 and {}",
+                        tetris::MAX_PREVIEWS
+                    ))?;
+            }
+            "--replay" => {
+                let path = args.next().ok_or("--replay needs a file")?;
+                let text = fs::read_to_string(&path)
+                    .map_err(|err| format!("could not read {}: {}", path, err))?;
+                options.replay =
+                    Some(Replay::parse(&text).map_err(|msg| format!("{}: {}", path, msg))?);
+            }
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        }
+    }
+    Ok(options)
+}
+
 fn main() -> std::io::Result<()> {
+    let defaults = Settings::default();
+    let config = match config::load(defaults.handling) {
+        Ok(config) => config,
+        Err(msg) => {
+            eprintln!("invalid config: {}", msg);
+            std::process::exit(2);
+        }
+    };
+    let settings = Settings {
+        handling: config.handling,
+        ..defaults
+    };
+    let options = match parse_args(settings) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            eprintln!(
+                "usage: tetris-tui-vanilla [--randomizer bag|classic|tgm] [--seed SEED] [--previews 1-6] \
+                [--das FRAMES] [--arr FRAMES] [--sdf FACTOR] [--lines LINES] [--zen-speed 1-20] \
+                [--garbage clean|messy] [--dig-rows 1-15] [--replay FILE]"
+            );
+            std::process::exit(2);
+        }
+    };
+
     let _clean_up = CleanUp;
     enable_raw_mode()?;
 
     let mut stdout = stdout();
+    // Terminals with the kitty keyboard protocol report key releases, needed for auto repeat
+    let key_releases = supports_keyboard_enhancement().unwrap_or(false);
+    if key_releases {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                    | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+            )
+        )?;
+    }
     execute!(
         stdout,
+        EnableBracketedPaste,
         cursor::Hide,
         Clear(ClearType::All),
         cursor::MoveTo(0, 0)
     )?;
 
-    Tetris::new().play();
+    let result = match options.replay {
+        Some(replay) => watch(Playback::new(replay), &config.keymap, key_releases),
+        None => play(
+            &mut Tetris::new(options.settings),
+            &config.keymap,
+            key_releases,
+        ),
+    };
 
     execute!(
         stdout,
@@ -42,568 +201,459 @@ fn main() -> std::io::Result<()> {
         Clear(ClearType::All),
         cursor::MoveTo(0, 0)
     )?;
-    Ok(())
+    result
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Copy)]
-struct Coordinates {
-    row: usize,
-    col: usize,
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum GameEvent {
+    Tick,
+    // A key press, with the action it is bound to if any
+    Key(event::KeyEvent, Option<Action>),
+    Release(KeyEvent),
+    Resize,
+    Paste(String),
+    Error(String),
 }
 
-impl Coordinates {
-    fn new(row: usize, col: usize) -> Coordinates {
-        Coordinates { row, col }
-    }
-
-    fn down(mut self, num: usize) -> Coordinates {
-        self.row += num;
-        self
-    }
-
-    fn up(mut self, num: usize) -> Result<Coordinates, ()> {
-        let Some(_num) = self.row.checked_sub(num) else {
-            return Err(());
-        };
-        self.row = _num;
-        Ok(self)
-    }
-
-    fn left(mut self, num: usize) -> Result<Coordinates, ()> {
-        let Some(_num) = self.col.checked_sub(num) else {
-            return Err(());
-        };
-        self.col = _num;
-        Ok(self)
+fn spawn_threads(
+    state: &Arc<Mutex<GameState>>,
+    keymap: &Keymap,
+    key_releases: bool,
+) -> mpsc::Receiver<GameEvent> {
+    let (tx, rx) = mpsc::channel();
+
+    {
+        let tx = tx.clone();
+        let state = Arc::clone(state);
+        thread::spawn(move || {
+            let frame = Duration::from_secs(1) / tetris::FPS;
+            let start = Instant::now();
+            for frames in 1.. {
+                // Sleep until the frame deadline so oversleeping doesn't accumulate
+                thread::sleep((start + frame * frames).saturating_duration_since(Instant::now()));
+                let game_state = state.lock().unwrap();
+                if *game_state == GameState::Playing && tx.send(GameEvent::Tick).is_err() {
+                    break;
+                }
+            }
+        });
     }
 
-    fn right(mut self, num: usize) -> Coordinates {
-        self.col += num;
-        self
+    {
+        let keymap = keymap.clone();
+        thread::spawn(move || read_input(tx, &keymap, key_releases));
     }
-}
 
-#[derive(Clone, Debug, PartialEq, Eq, Copy)]
-struct Block {
-    position: [Coordinates; 4],
-    color: Color,
-    piece: Piece,
-    rotation_pos: usize,
+    rx
 }
 
-impl Block {
-    fn new() -> Block {
-        let color: Color = rand::random();
-        let coor = Coordinates::new(3, COLS / 2 - 1);
-        let piece: Piece = rand::random();
-        let rotation_pos = rand::thread_rng().gen_range(0..4);
-        let mut position = get_piece_position(piece, rotation_pos, coor).unwrap();
-        if position.iter().all(|pos| pos.row != 4) {
-            position = position.map(|pos| pos.down(1));
-        }
-        Block {
-            position,
-            color,
-            piece,
-            rotation_pos,
-        }
-    }
-
-    fn down(&mut self) {
-        for pos in self.position.iter_mut() {
-            pos.row += 1
+fn play(tetris: &mut Tetris, keymap: &Keymap, key_releases: bool) -> std::io::Result<()> {
+    let state = Arc::new(Mutex::new(tetris.state().clone()));
+    let rx = spawn_threads(&state, keymap, key_releases);
+
+    // A table that could not be read is never saved over, so no new scores are taken
+    let (mut high_scores, scores_error) = match HighScores::load() {
+        Ok(high_scores) => (high_scores, None),
+        Err(err) => (
+            HighScores::default(),
+            Some(format!("Could not read the high scores: {}", err)),
+        ),
+    };
+    let mut showing_scores = false;
+    // A score that made the table, waiting for the player's name
+    let mut entry: Option<Score> = None;
+    // Split times of the best game in the mode, to race against
+    let mut best_splits: Vec<u64> = Vec::new();
+    let mut footer = String::new();
+    let mut screen = String::new();
+    loop {
+        let event = rx.recv().unwrap_or_else(|err| panic!("{}", err));
+        let from_state = tetris.state().clone();
+        match &event {
+            GameEvent::Error(msg) => return Err(std::io::Error::other(msg.clone())),
+            GameEvent::Resize => {
+                execute!(stdout(), Clear(ClearType::All))?;
+                screen.clear();
+            }
+            _ => (),
         }
-    }
-
-    fn left(&mut self) {
-        for pos in self.position.iter_mut() {
-            pos.col -= 1
+        let mut input = None;
+        if let Some(score) = &mut entry {
+            match edit_name(&mut score.name, &event) {
+                NameEntry::Typing => (),
+                NameEntry::Quit => break,
+                NameEntry::Cancel => entry = None,
+                NameEntry::Done => {
+                    let rank = high_scores.insert(entry.take().unwrap());
+                    footer = match (rank, high_scores.save()) {
+                        (Some(rank), Ok(())) => format!("High score #{} saved", rank),
+                        (_, Err(err)) => format!("Could not save the high score: {}", err),
+                        (None, Ok(())) => String::new(),
+                    };
+                }
+            }
+        } else if showing_scores {
+            if let GameEvent::Key(..) = event {
+                showing_scores = false;
+                execute!(stdout(), Clear(ClearType::All))?;
+            }
+        } else {
+            input = match event {
+                GameEvent::Key(_, Some(Action::Game(KeyEvent::Quit))) => break,
+                GameEvent::Key(
+                    _,
+                    Some(Action::Game(key @ (KeyEvent::RotateClockwise | KeyEvent::Down))),
+                ) if *tetris.state() == GameState::Menu => {
+                    let up = key == KeyEvent::RotateClockwise;
+                    select_mode(tetris, if up { GAME_MODES.len() - 1 } else { 1 });
+                    None
+                }
+                GameEvent::Key(_, Some(Action::Game(key @ (KeyEvent::Left | KeyEvent::Right))))
+                    if *tetris.state() == GameState::Menu =>
+                {
+                    change_option(tetris, key);
+                    None
+                }
+                GameEvent::Key(_, Some(Action::Game(KeyEvent::Pause)))
+                    if tetris.state().is_over() =>
+                {
+                    *tetris = Tetris::new(tetris.settings());
+                    None
+                }
+                GameEvent::Key(_, Some(Action::Scores)) => {
+                    if *tetris.state() == GameState::Menu {
+                        showing_scores = true;
+                        execute!(stdout(), Clear(ClearType::All))?;
+                    }
+                    None
+                }
+                GameEvent::Key(_, Some(Action::Game(key))) => Some(Input::Press(key)),
+                GameEvent::Release(key) => Some(Input::Release(key)),
+                GameEvent::Tick => Some(Input::Tick),
+                _ => None,
+            };
         }
-    }
-
-    fn right(&mut self) {
-        for pos in self.position.iter_mut() {
-            pos.col += 1
+        if let Some(input) = input {
+            tetris.step(input);
+            let ended = tetris
+                .events()
+                .find(|event| matches!(event, tetris::Event::GameOver | tetris::Event::Finished));
+            if let Some(event) = ended {
+                // Losing the replay is not worth interrupting the game over
+                let _ = save_replay(&tetris.replay());
+                let score = Score {
+                    mode: tetris.settings().mode,
+                    name: String::new(),
+                    points: tetris.points(),
+                    lines: tetris.lines(),
+                    level: tetris.level(),
+                    frames: tetris.frame(),
+                    date: scores::today(),
+                    splits: tetris.splits().to_vec(),
+                };
+                // A race only counts once it is finished
+                let counts =
+                    event == tetris::Event::Finished || score.mode.rank_by() != RankBy::Time;
+                if let Some(err) = &scores_error {
+                    footer = err.clone();
+                } else if counts && high_scores.qualifies(&score) {
+                    entry = Some(score);
+                }
+            }
         }
-    }
-
-    fn rotate(&mut self) {
-        let rotation_pos = (self.rotation_pos + 1) % 4;
-        let position = get_piece_position(self.piece, rotation_pos, self.position[0]);
-        if let Ok(pos) = position {
-            self.rotation_pos = rotation_pos;
-            self.position = pos;
+        if from_state != *tetris.state() {
+            if from_state == GameState::Menu || *tetris.state() == GameState::Menu {
+                execute!(
+                    stdout(),
+                    cursor::Hide,
+                    Clear(ClearType::All),
+                    cursor::MoveTo(0, 0)
+                )?;
+            }
+            if from_state.is_over() {
+                footer.clear();
+            }
+            if from_state != GameState::Pause && *tetris.state() == GameState::Playing {
+                best_splits = high_scores
+                    .best(tetris.settings().mode)
+                    .map_or(Vec::new(), |score| score.splits.clone());
+            }
+            *state.lock().unwrap() = tetris.state().clone();
         }
-    }
-
-    fn display(&self) -> Vec<String> {
-        let mut matrix = [[Square::Empty; COLS / 2 + 2]; 4];
-        for i in 0..4 {
-            matrix[self.position[i].row - 1][self.position[i].col] = Square::Occupied(self.color);
+        let output = if showing_scores {
+            let mut table = high_scores.table(tetris.settings().mode);
+            table.push(String::new());
+            table.push(String::from("Press any key to go back"));
+            table.join("\r\n")
+        } else if *tetris.state() == GameState::Menu {
+            menu(keymap, tetris.settings())
+        } else {
+            let footer = match &entry {
+                Some(score) => format!("NEW HIGH SCORE! Enter your name: {}_", score.name),
+                None if tetris.state().is_over() => {
+                    let hint = restart_hint(keymap);
+                    if footer.is_empty() {
+                        hint
+                    } else {
+                        format!("{}. {}", footer, hint)
+                    }
+                }
+                None => footer.clone(),
+            };
+            // Races show their split times against the best game
+            if tetris.settings().mode.rank_by() == RankBy::Time {
+                format!(
+                    "{}\r\n{:<80}\r\n{:<64}",
+                    screen_lines(tetris),
+                    scores::splits_line(tetris.splits(), &best_splits),
+                    footer
+                )
+            } else {
+                format!("{}\r\n{:<64}", screen_lines(tetris), footer)
+            }
+        };
+        if screen != output {
+            screen = output;
+            execute!(stdout(), cursor::MoveTo(0, 0))?;
+            println!("{}", screen);
         }
-        matrix
-            .iter_mut()
-            .map(|val| val.iter().map(|num| num.to_string()).collect::<String>())
-            .collect::<Vec<String>>()
     }
+    Ok(())
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Copy)]
-enum Piece {
-    I,
-    J,
-    L,
-    O,
-    S,
-    T,
-    Z,
-}
-
-impl Distribution<Piece> for Standard {
-    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> Piece {
-        match rng.gen_range(0..=6) {
-            0 => Piece::I,
-            1 => Piece::J,
-            2 => Piece::L,
-            3 => Piece::O,
-            4 => Piece::S,
-            5 => Piece::T,
-            _ => Piece::Z,
-        }
-    }
+enum NameEntry {
+    Typing,
+    Done,
+    Cancel,
+    Quit,
 }
 
-fn get_piece_position(piece: Piece, pos: usize, coor: Coordinates) -> Result<[Coordinates; 4], ()> {
-    match (piece, pos) {
-        (Piece::I, p) if p % 2 == 1 => Ok([coor, coor.up(1)?, coor.up(2)?, coor.down(1)]),
-        (Piece::I, p) if p % 2 == 0 => Ok([coor, coor.right(1), coor.right(2), coor.left(1)?]),
-        (Piece::J, 0) => Ok([coor, coor.left(1)?, coor.right(1), coor.down(1).right(1)]),
-        (Piece::J, 1) => Ok([coor, coor.up(1)?, coor.down(1), coor.down(1).left(1)?]),
-        (Piece::J, 2) => Ok([coor, coor.right(1), coor.left(1)?, coor.left(1)?.up(1)?]),
-        (Piece::J, 3) => Ok([coor, coor.up(1)?, coor.right(1).up(1)?, coor.down(1)]),
-        (Piece::L, 0) => Ok([coor, coor.right(1), coor.left(1)?, coor.right(1).up(1)?]),
-        (Piece::L, 1) => Ok([coor, coor.up(1)?, coor.down(1), coor.down(1).right(1)]),
-        (Piece::L, 2) => Ok([coor, coor.right(1), coor.left(1)?, coor.down(1).left(1)?]),
-        (Piece::L, 3) => Ok([coor, coor.down(1), coor.up(1)?, coor.up(1)?.left(1)?]),
-        (Piece::T, 0) => Ok([coor, coor.left(1)?, coor.right(1), coor.down(1)]),
-        (Piece::T, 1) => Ok([coor, coor.down(1), coor.up(1)?, coor.left(1)?]),
-        (Piece::T, 2) => Ok([coor, coor.right(1), coor.left(1)?, coor.up(1)?]),
-        (Piece::T, 3) => Ok([coor, coor.up(1)?, coor.down(1), coor.right(1)]),
-        (Piece::S, p) if p % 2 == 1 => {
-            Ok([coor, coor.up(1)?, coor.right(1), coor.down(1).right(1)])
-        }
-        (Piece::S, _) => Ok([coor, coor.left(1)?, coor.up(1)?, coor.right(1).up(1)?]),
-        (Piece::Z, p) if p % 2 == 1 => {
-            Ok([coor, coor.down(1), coor.right(1), coor.right(1).up(1)?])
+// Types keys and pasted text into the name, Enter confirms and Esc skips the high score
+fn edit_name(name: &mut String, event: &GameEvent) -> NameEntry {
+    let typed: Vec<char> = match event {
+        GameEvent::Key(key, _) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            return match key.code {
+                KeyCode::Char('c') => NameEntry::Quit,
+                _ => NameEntry::Typing,
+            }
         }
-        (Piece::Z, _) => Ok([coor, coor.left(1)?, coor.down(1), coor.down(1).right(1)]),
-        (Piece::O, _) => Ok([coor, coor.right(1), coor.up(1)?, coor.right(1).up(1)?]),
-        (_, _) => Err(()),
-    }
-}
-
-#[derive(Clone, Debug, PartialEq, Eq, Copy)]
-enum Color {
-    Red,
-    Blue,
-    Orange,
-    Yellow,
-    Green,
-    Violet,
-    Brown,
-}
-
-impl Distribution<Color> for Standard {
-    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> Color {
-        match rng.gen_range(0..=6) {
-            0 => Color::Red,
-            1 => Color::Blue,
-            2 => Color::Orange,
-            3 => Color::Yellow,
-            4 => Color::Green,
-            5 => Color::Violet,
-            _ => Color::Brown,
+        GameEvent::Key(key, _) => match key.code {
+            KeyCode::Enter => {
+                *name = name.trim().to_string();
+                if name.is_empty() {
+                    *name = String::from("player");
+                }
+                return NameEntry::Done;
+            }
+            KeyCode::Esc => return NameEntry::Cancel,
+            KeyCode::Backspace => {
+                name.pop();
+                return NameEntry::Typing;
+            }
+            KeyCode::Char(c) => vec![c],
+            _ => Vec::new(),
+        },
+        GameEvent::Paste(text) => text.chars().collect(),
+        _ => Vec::new(),
+    };
+    for c in typed {
+        // Leading spaces are most likely hard drops mashed while topping out
+        let leading_space = c.is_whitespace() && name.is_empty();
+        if !c.is_control() && !leading_space && name.chars().count() < scores::MAX_NAME {
+            name.push(c);
         }
     }
+    NameEntry::Typing
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Copy)]
-enum Square {
-    Empty,
-    Occupied(Color),
-}
-
-impl ToString for Square {
-    fn to_string(&self) -> String {
-        match self {
-            Square::Empty => String::from("  "),
-            Square::Occupied(Color::Red) => String::from("\u{1F7E5}"),
-            Square::Occupied(Color::Blue) => String::from("\u{1F7E6}"),
-            Square::Occupied(Color::Orange) => String::from("\u{1F7E7}"),
-            Square::Occupied(Color::Yellow) => String::from("\u{1F7E8}"),
-            Square::Occupied(Color::Green) => String::from("\u{1F7E9}"),
-            Square::Occupied(Color::Violet) => String::from("\u{1F7EA}"),
-            Square::Occupied(Color::Brown) => String::from("\u{1F7EB}"),
+const SPEEDS: [usize; 5] = [1, 2, 4, 8, 16];
+
+// Plays a replay back: space pauses, → steps a frame while paused, + and - change the speed
+fn watch(mut playback: Playback, keymap: &Keymap, key_releases: bool) -> std::io::Result<()> {
+    let state = Arc::new(Mutex::new(GameState::Playing));
+    let rx = spawn_threads(&state, keymap, key_releases);
+
+    let mut screen = String::new();
+    let mut paused = false;
+    let mut speed = 0;
+    loop {
+        let event = rx.recv().unwrap_or_else(|err| panic!("{}", err));
+        match event {
+            GameEvent::Error(msg) => return Err(std::io::Error::other(msg)),
+            GameEvent::Resize => {
+                execute!(stdout(), Clear(ClearType::All))?;
+                screen.clear();
+            }
+            GameEvent::Tick if !paused => {
+                for _ in 0..SPEEDS[speed] {
+                    playback.advance();
+                }
+            }
+            GameEvent::Key(_, Some(Action::Game(KeyEvent::Quit))) => break,
+            GameEvent::Key(_, Some(Action::Game(KeyEvent::Pause))) => paused = !paused,
+            GameEvent::Key(_, Some(Action::Game(KeyEvent::Right))) if paused => playback.advance(),
+            GameEvent::Key(key, _) => match key.code {
+                KeyCode::Char('+') => speed = (speed + 1).min(SPEEDS.len() - 1),
+                KeyCode::Char('-') => speed = speed.saturating_sub(1),
+                _ => (),
+            },
+            _ => (),
         }
-    }
-}
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-enum GameState {
-    Playing,
-    Pause,
-    Menu,
-    EndScreen,
-}
-
-impl GameState {
-    fn print_message(&self) -> Vec<String> {
-        let message = match self {
-            GameState::Pause => [String::from("GAME PAUSED"), String::from("")],
-            GameState::EndScreen => [
-                String::from("YOU LOST!"),
-                String::from("Press p to restart or q to quit"),
-            ],
-            GameState::Playing | GameState::Menu => [String::from(""), String::from("")],
+        let status = match (playback.is_finished(), paused) {
+            (true, _) => String::from("REPLAY OVER"),
+            (false, true) => String::from("PAUSED"),
+            (false, false) => format!("x{}", SPEEDS[speed]),
         };
-        let longest = "Press p to restart or q to quit".len();
-        message
-            .into_iter()
-            .map(|s| format!("{}{}", s, &" ".repeat(longest - s.len())))
-            .collect::<Vec<String>>()
+        let output = format!(
+            "{}\r\nREPLAY  frame {:<8}{:<14}{} pause  {} step  [+/-] speed",
+            screen_lines(playback.tetris()),
+            playback.tetris().frame(),
+            status,
+            keymap.key(KeyEvent::Pause),
+            keymap.key(KeyEvent::Right)
+        );
+        if screen != output {
+            screen = output;
+            execute!(stdout(), cursor::MoveTo(0, 0))?;
+            println!("{}", screen);
+        }
     }
+    Ok(())
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-enum GameEvent {
-    Tick,
-    Key(KeyEvent),
-}
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-enum KeyEvent {
-    Down,
-    Left,
-    Right,
-    Rotate,
-    Quit,
-    Play,
-    Pause,
-}
-
-const COLS: usize = 10;
-const ROWS: usize = 23;
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-struct Tetris {
-    board: Vec<Vec<Square>>,
-    current_block: Block,
-    next_block: Block,
-    points: usize,
-    state: GameState,
+fn restart_hint(keymap: &Keymap) -> String {
+    format!(
+        "{}: restart, {}: menu, {}: quit",
+        keymap.key(KeyEvent::Play),
+        keymap.key(KeyEvent::Pause),
+        keymap.key(KeyEvent::Quit)
+    )
 }
 
-impl Display for Tetris {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut output = self.board.clone();
-        for i in 0..4 {
-            output[self.current_block.position[i].row][self.current_block.position[i].col] =
-                Square::Occupied(self.current_block.color);
-        }
-        let next = self.next_block.display();
-        let output: Vec<String> = output
-            .iter_mut()
-            .skip(4)
-            .enumerate()
-            .map(|(row, val)| {
-                let ret: Vec<String> = val.iter().map(|num| num.to_string()).collect();
-                let right_menu = match row {
-                    5 => format!("    Points: {} ", &self.points.to_string()),
-                    7 => format!("    {} ", &self.state.print_message()[0]),
-                    8 => format!("    {} ", &self.state.print_message()[1]),
-                    10 => next[0].to_string(),
-                    11 => next[1].to_string(),
-                    12 => next[2].to_string(),
-                    13 => next[3].to_string(),
-                    _ => String::new(),
-                };
-                format!("\u{2590}{}\u{258C}{}", ret.join(""), right_menu)
-            })
-            .collect();
-        write!(
-            f,
-            "{}\n\r{}",
-            output.join("\n\r"),
-            "\u{2594}".repeat(COLS * 2 + 2),
-        )
-    }
+// Raw mode doesn't return the cursor on a line feed
+fn screen_lines(tetris: &Tetris) -> String {
+    tetris.to_string().replace('\n', "\r\n")
 }
 
-impl Tetris {
-    fn new() -> Tetris {
-        Tetris {
-            board: vec![vec![Square::Empty; COLS]; ROWS],
-            current_block: Block::new(),
-            next_block: Block::new(),
-            points: 0,
-            state: GameState::Menu,
-        }
-    }
-
-    fn play(&mut self) {
-        let (tx, rx) = mpsc::channel();
-
-        let state = Arc::new(Mutex::new(GameState::Playing));
-
-        {
-            let tx = tx.clone();
-            let state = Arc::clone(&state);
-            thread::spawn(move || loop {
-                thread::sleep(Duration::from_millis(1000));
-                let game_state = state.lock().unwrap();
-                if *game_state == GameState::Playing {
-                    tx.send(GameEvent::Tick).unwrap();
-                }
-            });
-        }
-
-        {
-            let tx = tx.clone();
-            thread::spawn(move || {
-                let stdin = &mut std::io::stdin();
-
-                loop {
-                    if let Some(k) = get_input(stdin) {
-                        tx.send(GameEvent::Key(k)).unwrap()
-                    }
-                }
-            });
-        }
-
-        self.draw_menu();
-        loop {
-            match self.state {
-                GameState::Menu => match rx.recv() {
-                    Ok(GameEvent::Key(key)) => match key {
-                        KeyEvent::Play => {
-                            execute!(
-                                stdout(),
-                                cursor::Hide,
-                                Clear(ClearType::All),
-                                cursor::MoveTo(0, 0)
-                            )
-                            .unwrap();
-                            self.change_state(&state, GameState::Playing);
-                            self.current_block = Block::new();
-                        }
-                        KeyEvent::Quit => break,
-                        _ => (),
-                    },
-                    Ok(GameEvent::Tick) => (),
-                    Err(err) => panic!("{}", err),
-                },
-                GameState::Playing => match rx.recv() {
-                    Ok(GameEvent::Key(key)) => match key {
-                        KeyEvent::Quit => break,
-                        KeyEvent::Left => self.block_left(),
-                        KeyEvent::Right => self.block_right(),
-                        KeyEvent::Down => self.block_down(),
-                        KeyEvent::Rotate => self.block_rotate(),
-                        KeyEvent::Play => (),
-                        KeyEvent::Pause => self.change_state(&state, GameState::Pause),
-                    },
-                    Ok(GameEvent::Tick) => {
-                        if Err(()) == self.tick() {
-                            self.state = GameState::EndScreen;
-                        }
-                    }
-                    Err(err) => panic!("{}", err),
-                },
-                GameState::Pause => match rx.recv() {
-                    Ok(GameEvent::Key(key)) => {
-                        match key {
-                            KeyEvent::Quit => break,
-                            KeyEvent::Pause => self.change_state(&state, GameState::Playing),
-                            _ => (),
-                        };
-                    }
-                    Ok(GameEvent::Tick) => (),
-                    Err(err) => panic!("{}", err),
-                },
-                GameState::EndScreen => match rx.recv() {
-                    Ok(GameEvent::Key(key)) => {
-                        match key {
-                            KeyEvent::Quit => break,
-                            KeyEvent::Play => {
-                                *self = Tetris::new();
-                                self.change_state(&state, GameState::Playing);
-                                self.current_block = Block::new();
-                            }
-                            _ => (),
-                        };
-                    }
-                    Ok(GameEvent::Tick) => (),
-                    Err(err) => panic!("{}", err),
-                },
-            }
-            if self.state != GameState::Menu {
-                execute!(stdout(), cursor::MoveTo(0, 0)).unwrap();
-                println!("{}", self);
+// Replays go to $XDG_DATA_HOME/tetris-tui-vanilla/replays, named after the time the game ended.
+// Games ending in the same second get a counter so none is overwritten
+fn save_replay(replay: &Replay) -> std::io::Result<()> {
+    let dir = config::data_dir()
+        .ok_or(std::io::Error::other("no data directory"))?
+        .join("replays");
+    fs::create_dir_all(&dir)?;
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut path = dir.join(format!("{}.replay", time.as_secs()));
+    let mut count = 1;
+    loop {
+        match fs::File::create_new(&path) {
+            Ok(mut file) => return file.write_all(replay.to_string().as_bytes()),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                count += 1;
+                path = dir.join(format!("{}-{}.replay", time.as_secs(), count));
             }
+            Err(err) => return Err(err),
         }
     }
+}
 
-    fn change_state(&mut self, state: &Arc<Mutex<GameState>>, new_state: GameState) {
-        let mut game_state = state.lock().unwrap();
-        *game_state = new_state.clone();
-        self.state = new_state;
-    }
+// Moves the menu selection by the given number of modes, wrapping around
+fn select_mode(tetris: &mut Tetris, offset: usize) {
+    let settings = tetris.settings();
+    let index = GAME_MODES
+        .iter()
+        .position(|mode| *mode == settings.mode)
+        .unwrap_or_default();
+    *tetris = Tetris::new(Settings {
+        mode: GAME_MODES[(index + offset) % GAME_MODES.len()],
+        ..settings
+    });
+}
 
-    fn add_current_block(&mut self) {
-        for i in 0..4 {
-            self.board[self.current_block.position[i].row][self.current_block.position[i].col] =
-                Square::Occupied(self.current_block.color);
+// Changes the option of the selected mode: the speed of Zen or the kind of garbage
+fn change_option(tetris: &mut Tetris, key: KeyEvent) {
+    let mut settings = tetris.settings();
+    match settings.mode {
+        GameMode::Zen => {
+            let zen_speed = match key {
+                KeyEvent::Left => settings.zen_speed - 1,
+                _ => settings.zen_speed + 1,
+            };
+            settings.zen_speed = zen_speed.clamp(1, tetris::MAX_ZEN_SPEED);
         }
-    }
-
-    fn tick(&mut self) -> Result<(), ()> {
-        if !self.can_block_move(KeyEvent::Down) {
-            self.add_current_block();
-            self.remove_lines_completed();
-            if self.is_end() || self.is_collision(&self.next_block) {
-                return Err(());
+        GameMode::Dig | GameMode::Survival => {
+            settings.garbage = match settings.garbage {
+                GarbageKind::Clean => GarbageKind::Messy,
+                GarbageKind::Messy => GarbageKind::Clean,
             }
-            self.current_block = self.next_block;
-            self.next_block = Block::new();
-        } else {
-            self.current_block.down();
-        }
-        Ok(())
-    }
-
-    fn block_down(&mut self) {
-        if self.can_block_move(KeyEvent::Down) {
-            self.current_block.down();
-        }
-    }
-
-    fn block_left(&mut self) {
-        if self.can_block_move(KeyEvent::Left) {
-            self.current_block.left();
-        }
-    }
-
-    fn block_right(&mut self) {
-        if self.can_block_move(KeyEvent::Right) {
-            self.current_block.right();
         }
+        _ => return,
     }
+    *tetris = Tetris::new(settings);
+}
 
-    fn block_rotate(&mut self) {
-        let mut block = self.current_block;
-        block.rotate();
-        if !self.is_collision(&block) {
-            self.current_block = block;
-        }
-    }
-
-    fn can_block_move(&self, movement: KeyEvent) -> bool {
-        self.current_block
-            .position
-            .into_iter()
-            .map(|sq| match movement {
-                KeyEvent::Down => Ok(sq.down(1)),
-                KeyEvent::Right => Ok(sq.right(1)),
-                KeyEvent::Left => Ok(sq.left(1)?),
-                _ => Err(()),
-            })
-            .all(|sq| {
-                sq.is_ok()
-                    && sq.unwrap().col < COLS
-                    && sq.unwrap().row < ROWS
-                    && !self.is_occupied(sq.unwrap())
-            })
-    }
-
-    fn draw_menu(&self) {
-        execute!(stdout(), cursor::MoveTo(0, 0)).unwrap();
-        println!(
-            "TETRIS\n\r
-
-KEYS:\n\r
-P => Play\n\r
-A (or ←) => Move Block to the left\n\r
-D (or →) => Move Block to the right\n\r
-S (or ↓) => Move Block down\n\r
-W (or ↑) => Rotate Block\n\r
-
-[SPACE] => Pause\n\r
-Q => Quit\n\r"
+fn menu(keymap: &Keymap, settings: Settings) -> String {
+    let mut menu = String::from("TETRIS\r\n\r\n\r\nMODE:\r\n\r\n");
+    for mode in GAME_MODES {
+        let cursor = if mode == settings.mode { '>' } else { ' ' };
+        let option = match mode {
+            GameMode::Zen => format!(" (speed {})", settings.zen_speed),
+            GameMode::Dig => format!(" ({} {} rows)", settings.dig_rows, settings.garbage.name()),
+            GameMode::Survival => format!(" ({} garbage)", settings.garbage.name()),
+            _ => String::new(),
+        };
+        menu += &format!(
+            "{} {:<10}{}{:<16}\r\n",
+            cursor,
+            mode.name().to_uppercase(),
+            mode.description(),
+            option
         );
     }
-
-    fn is_occupied(&self, coor: Coordinates) -> bool {
-        self.board[coor.row][coor.col] != Square::Empty
-    }
-
-    fn is_collision(&self, block: &Block) -> bool {
-        block
-            .position
-            .into_iter()
-            .any(|sq| sq.col >= COLS || sq.row >= ROWS || self.is_occupied(sq))
-    }
-
-    fn remove_lines_completed(&mut self) {
-        self.board
-            .retain(|val| val.iter().any(|sq| *sq == Square::Empty));
-        let deleted = ROWS - self.board.len();
-        if deleted > 0 {
-            self.board
-                .splice(0..0, vec![vec![Square::Empty; COLS]; deleted]);
-            self.points += deleted;
-        }
-    }
-
-    fn is_end(&self) -> bool {
-        self.board
-            .iter()
-            .rev()
-            .skip(ROWS - 2)
-            .any(|val| val.iter().any(|sq| *sq != Square::Empty))
-    }
+    menu += &format!(
+        "\r\n{} / {} => Choose mode\r\n{} / {} => Zen speed, garbage\r\n\r\n\r\nKEYS:\r\n\r\n",
+        keymap.key(KeyEvent::RotateClockwise),
+        keymap.key(KeyEvent::Down),
+        keymap.key(KeyEvent::Left),
+        keymap.key(KeyEvent::Right)
+    );
+    for line in keymap.help() {
+        menu += &format!("{}\r\n\r\n", line);
+    }
+    menu
 }
 
-fn get_input(stdin: &mut std::io::Stdin) -> Option<KeyEvent> {
-    use std::io::Read;
-
-    let c = &mut [0u8];
-    match stdin.read(c) {
-        Ok(_) => match std::str::from_utf8(c) {
-            Ok("w") => Some(KeyEvent::Rotate),
-            Ok("s") => Some(KeyEvent::Down),
-            Ok("a") => Some(KeyEvent::Left),
-            Ok("d") => Some(KeyEvent::Right),
-            Ok("q") => Some(KeyEvent::Quit),
-            Ok("p") => Some(KeyEvent::Play),
-            Ok(" ") => Some(KeyEvent::Pause),
-            Ok("\x1b") => {
-                let code = &mut [0u8; 2];
-                match stdin.read(code) {
-                    Ok(_) => match std::str::from_utf8(code) {
-                        Ok("[A") => Some(KeyEvent::Rotate),
-                        Ok("[B") => Some(KeyEvent::Down),
-                        Ok("[C") => Some(KeyEvent::Right),
-                        Ok("[D") => Some(KeyEvent::Left),
-                        _ => None,
-                    },
-                    Err(msg) => {
-                        panic!("{}", format!("could not read from standard in: {}", msg))
-                    }
+fn read_input(tx: mpsc::Sender<GameEvent>, keymap: &Keymap, key_releases: bool) {
+    loop {
+        let events = match event::read() {
+            Ok(Event::Key(key)) => match (key.kind, keymap.action(&key)) {
+                // Without release events every press is a tap
+                (KeyEventKind::Press, Some(Action::Game(action))) if !key_releases => vec![
+                    GameEvent::Key(key, Some(Action::Game(action.clone()))),
+                    GameEvent::Release(action),
+                ],
+                (KeyEventKind::Press, action) => vec![GameEvent::Key(key, action)],
+                (KeyEventKind::Release, Some(Action::Game(action))) => {
+                    vec![GameEvent::Release(action)]
                 }
+                _ => Vec::new(),
+            },
+            Ok(Event::Resize(_, _)) => vec![GameEvent::Resize],
+            Ok(Event::Paste(text)) => vec![GameEvent::Paste(text)],
+            Ok(_) => Vec::new(),
+            Err(msg) => {
+                let msg = format!("could not read from standard in: {}", msg);
+                let _ = tx.send(GameEvent::Error(msg));
+                return;
             }
-            _ => None,
-        },
-        Err(msg) => panic!("{}", format!("could not read from standard in: {}", msg)),
+        };
+        for event in events {
+            if tx.send(event).is_err() {
+                return;
+            }
+        }
     }
 }
 
@@ -622,66 +672,3 @@ fn get_input(stdin: &mut std::io::Stdin) -> Option<KeyEvent> {
 /* fn move_cursor(row: usize, col: usize) {
     print!("\x1B[{0};{1}H", row, col);
 } */
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn create_block(col: usize, row: usize) -> Block {
-        Block {
-            position: [
-                Coordinates { row, col },
-                Coordinates { row, col },
-                Coordinates { row, col },
-                Coordinates { row, col },
-            ],
-            color: Color::Red,
-            piece: Piece::I,
-            rotation_pos: 1,
-        }
-    }
-
-    fn create_tetris(col: usize, row: usize) -> Tetris {
-        let block = create_block(col, row);
-
-        let board = vec![
-            vec![
-                Square::Empty,
-                Square::Empty,
-                Square::Empty,
-                Square::Occupied(Color::Blue),
-                Square::Occupied(Color::Blue),
-                Square::Occupied(Color::Blue),
-                Square::Occupied(Color::Blue),
-                Square::Empty,
-                Square::Empty,
-                Square::Empty
-            ];
-            ROWS
-        ];
-
-        Tetris {
-            board,
-            current_block: block,
-            next_block: block,
-            points: 1,
-            state: GameState::Playing,
-        }
-    }
-
-    #[test]
-    fn test_add_block() {
-        let mut tetris = create_tetris(8, 8);
-        assert!(!tetris.is_occupied(Coordinates::new(8, 8)));
-        tetris.add_current_block();
-        assert!(tetris.is_occupied(Coordinates::new(8, 8)));
-    }
-
-    #[test]
-    fn test_collision() {
-        let tetris = create_tetris(8, 8);
-        assert!(tetris.is_collision(&create_block(3, 5)));
-        assert!(!tetris.is_collision(&create_block(2, 5)));
-        assert!(tetris.is_collision(&create_block(10, 5)));
-    }
-}
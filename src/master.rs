@@ -0,0 +1,159 @@
+use crate::FPS;
+
+// The level counter stops here and the game is won
+pub const MAX_LEVEL: usize = 999;
+
+// Delays of a section of levels, in frames
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub struct Timing {
+    // Before the next piece spawns
+    pub are: u32,
+    // Before the next piece spawns after clearing lines, on top of the clear delay
+    pub line_are: u32,
+    pub lock: u32,
+    pub clear: u32,
+}
+
+pub fn timing(level: usize) -> Timing {
+    let (are, line_are, lock, clear) = match level {
+        0..=99 => (18, 14, 30, 12),
+        100..=199 => (14, 8, 26, 6),
+        200..=299 => (14, 8, 22, 6),
+        300..=399 => (8, 8, 18, 6),
+        400..=499 => (7, 7, 15, 5),
+        _ => (6, 6, 15, 4),
+    };
+    Timing {
+        are,
+        line_are,
+        lock,
+        clear,
+    }
+}
+
+// A new piece moves the counter on, except past the end of a section or onto the last level
+pub fn piece_level(level: usize) -> usize {
+    if level % 100 == 99 || level >= MAX_LEVEL - 1 {
+        level
+    } else {
+        level + 1
+    }
+}
+
+// Points needed for each grade, TGM style from 9 up to S9
+const GRADES: [(usize, &str); 18] = [
+    (0, "9"),
+    (400, "8"),
+    (800, "7"),
+    (1400, "6"),
+    (2000, "5"),
+    (3500, "4"),
+    (5500, "3"),
+    (8000, "2"),
+    (12000, "1"),
+    (16000, "S1"),
+    (22000, "S2"),
+    (30000, "S3"),
+    (40000, "S4"),
+    (52000, "S5"),
+    (66000, "S6"),
+    (82000, "S7"),
+    (100000, "S8"),
+    (120000, "S9"),
+];
+
+// GM needs these points by these levels in these times, as minutes and seconds
+const GM_CHECKPOINTS: [(usize, usize, u64); 3] = [
+    (300, 12000, 4 * 60 + 15),
+    (500, 40000, 7 * 60 + 30),
+    (MAX_LEVEL, 126000, 13 * 60 + 30),
+];
+
+// Scoring and grading of a Master game
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Master {
+    // Grows with every clear in a row, by one less than twice the lines
+    combo: usize,
+    // Rows dropped by hand with the current piece
+    dropped: usize,
+    gm: bool,
+}
+
+impl Master {
+    pub fn new() -> Master {
+        Master {
+            combo: 1,
+            dropped: 0,
+            gm: true,
+        }
+    }
+
+    pub fn drop(&mut self, rows: usize) {
+        self.dropped += rows;
+    }
+
+    // Points of a locked piece: (ceil((level + lines) / 4) + dropped) * lines * combo, times 4 for
+    // a perfect clear
+    pub fn points(&mut self, level: usize, lines: usize, perfect: bool) -> usize {
+        let dropped = std::mem::take(&mut self.dropped);
+        if lines == 0 {
+            self.combo = 1;
+            return 0;
+        }
+        self.combo += 2 * lines - 2;
+        let bravo = if perfect { 4 } else { 1 };
+        ((level + lines).div_ceil(4) + dropped) * lines * self.combo * bravo
+    }
+
+    // Checks the GM requirements of the checkpoints passed going from one level to another
+    pub fn check(&mut self, from: usize, to: usize, points: usize, frame: u64) {
+        for (level, needed, seconds) in GM_CHECKPOINTS {
+            if from < level && to >= level && (points < needed || frame > seconds * FPS as u64) {
+                self.gm = false;
+            }
+        }
+    }
+
+    pub fn grade(&self, level: usize, points: usize) -> &'static str {
+        if self.gm && level >= MAX_LEVEL {
+            return "GM";
+        }
+        GRADES
+            .iter()
+            .rev()
+            .find(|(needed, _)| points >= *needed)
+            .map_or("9", |(_, grade)| grade)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_and_timing() {
+        assert_eq!(piece_level(0), 1);
+        assert_eq!(piece_level(199), 199);
+        assert_eq!(piece_level(998), 998);
+        assert_eq!(timing(99).lock, 30);
+        assert_eq!(timing(250).are, 14);
+        assert_eq!(timing(MAX_LEVEL).clear, 4);
+    }
+
+    #[test]
+    fn test_points_and_grades() {
+        let mut master = Master::new();
+        assert_eq!(master.points(10, 1, false), 3);
+        master.drop(2);
+        // A double in a row lifts the combo from 1 to 3
+        assert_eq!(master.points(11, 2, false), (4 + 2) * 2 * 3);
+        assert_eq!(master.points(13, 0, false), 0);
+        assert_eq!(master.points(13, 4, true), 5 * 4 * 7 * 4);
+
+        assert_eq!(master.grade(200, 399), "9");
+        assert_eq!(master.grade(200, 16000), "S1");
+        assert_eq!(master.grade(MAX_LEVEL, 130000), "GM");
+        master.check(290, 301, 11000, 60 * FPS as u64);
+        assert_eq!(master.grade(MAX_LEVEL, 130000), "S9");
+    }
+}
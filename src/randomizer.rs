@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::Piece;
+
+const PIECES: [Piece; 7] = [
+    Piece::I,
+    Piece::J,
+    Piece::L,
+    Piece::O,
+    Piece::S,
+    Piece::T,
+    Piece::Z,
+];
+
+// TGM2 (The Absolute Plus) rolls up to six times against a history starting as Z, S, S, Z
+const HISTORY_ROLLS: usize = 6;
+const HISTORY_START: [Piece; 4] = [Piece::Z, Piece::S, Piece::S, Piece::Z];
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub enum RandomizerKind {
+    Bag,
+    Classic,
+    History,
+}
+
+impl RandomizerKind {
+    pub fn from_name(name: &str) -> Option<RandomizerKind> {
+        match name {
+            "bag" => Some(RandomizerKind::Bag),
+            "classic" => Some(RandomizerKind::Classic),
+            "tgm" => Some(RandomizerKind::History),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RandomizerKind::Bag => "bag",
+            RandomizerKind::Classic => "classic",
+            RandomizerKind::History => "tgm",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Generator {
+    // Deals a shuffled set of the seven pieces before starting a new one
+    Bag(Vec<Piece>),
+    // Every piece is drawn uniformly, with no memory of the previous ones
+    Classic,
+    // TGM style: rerolls a piece that is among the last four dealt
+    History {
+        history: VecDeque<Piece>,
+        first: bool,
+    },
+}
+
+impl Generator {
+    fn new(kind: RandomizerKind) -> Generator {
+        match kind {
+            RandomizerKind::Bag => Generator::Bag(Vec::new()),
+            RandomizerKind::Classic => Generator::Classic,
+            RandomizerKind::History => Generator::History {
+                history: VecDeque::from(HISTORY_START),
+                first: true,
+            },
+        }
+    }
+
+    fn next_piece<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Piece {
+        match self {
+            Generator::Bag(bag) => {
+                if bag.is_empty() {
+                    *bag = PIECES.to_vec();
+                    bag.shuffle(rng);
+                }
+                bag.pop().unwrap()
+            }
+            Generator::Classic => rng.gen(),
+            Generator::History { history, first } => {
+                let piece = if std::mem::take(first) {
+                    // The first piece is never one that forces an overhang
+                    *[Piece::I, Piece::J, Piece::L, Piece::T]
+                        .choose(rng)
+                        .unwrap()
+                } else {
+                    let mut piece = rng.gen();
+                    for _ in 1..HISTORY_ROLLS {
+                        if !history.contains(&piece) {
+                            break;
+                        }
+                        piece = rng.gen();
+                    }
+                    piece
+                };
+                history.pop_front();
+                history.push_back(piece);
+                piece
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Randomizer {
+    generator: Generator,
+    queue: VecDeque<Piece>,
+    previews: usize,
+}
+
+impl Randomizer {
+    pub fn new<R: Rng + ?Sized>(kind: RandomizerKind, previews: usize, rng: &mut R) -> Randomizer {
+        let mut randomizer = Randomizer {
+            generator: Generator::new(kind),
+            queue: VecDeque::new(),
+            previews: previews.max(1),
+        };
+        randomizer.fill(rng);
+        randomizer
+    }
+
+    pub fn next<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Piece {
+        let piece = self.queue.pop_front().unwrap();
+        self.fill(rng);
+        piece
+    }
+
+    pub fn preview(&self) -> impl Iterator<Item = &Piece> {
+        self.queue.iter()
+    }
+
+    fn fill<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        while self.queue.len() < self.previews {
+            let piece = self.generator.next_piece(rng);
+            self.queue.push_back(piece);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_bag_deals_every_piece() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut randomizer = Randomizer::new(RandomizerKind::Bag, 1, &mut rng);
+        for _ in 0..3 {
+            let mut bag: Vec<Piece> = (0..7).map(|_| randomizer.next(&mut rng)).collect();
+            bag.sort_by_key(|piece| PIECES.iter().position(|p| p == piece));
+            assert_eq!(bag, PIECES);
+        }
+    }
+
+    #[test]
+    fn test_history_first_piece() {
+        for seed in 0..20 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let mut randomizer = Randomizer::new(RandomizerKind::History, 1, &mut rng);
+            assert!(![Piece::O, Piece::S, Piece::Z].contains(&randomizer.next(&mut rng)));
+        }
+    }
+
+    #[test]
+    fn test_history_starts_seeded() {
+        // With Z, S, S, Z in the history the early pieces are rarely S or Z
+        let snakes: usize = (0..200)
+            .map(|seed| {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                let mut randomizer = Randomizer::new(RandomizerKind::History, 1, &mut rng);
+                randomizer.next(&mut rng);
+                (0..2)
+                    .filter(|_| [Piece::S, Piece::Z].contains(&randomizer.next(&mut rng)))
+                    .count()
+            })
+            .sum();
+        assert!(snakes < 10, "{} S or Z pieces", snakes);
+    }
+
+    #[test]
+    fn test_preview_length() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut randomizer = Randomizer::new(RandomizerKind::Classic, 6, &mut rng);
+        let preview: Vec<Piece> = randomizer.preview().copied().collect();
+        assert_eq!(preview.len(), 6);
+        assert_eq!(randomizer.next(&mut rng), preview[0]);
+        assert_eq!(randomizer.preview().count(), 6);
+    }
+
+    #[test]
+    fn test_same_seed_same_pieces() {
+        let pieces = |seed| {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let mut randomizer = Randomizer::new(RandomizerKind::History, 1, &mut rng);
+            (0..50)
+                .map(|_| randomizer.next(&mut rng))
+                .collect::<Vec<Piece>>()
+        };
+        assert_eq!(pieces(42), pieces(42));
+        assert_ne!(pieces(42), pieces(43));
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::{self, Display};
+
+use crate::{
+    GameMode, GameState, GarbageKind, Input, KeyEvent, RandomizerKind, Settings, Tetris,
+    MAX_DIG_ROWS, MAX_PREVIEWS, MAX_ZEN_SPEED,
+};
+
+const HEADER: &str = "tetris-tui-vanilla replay";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    // Settings of the recorded game, always with its seed
+    pub settings: Settings,
+    // Key presses and releases stamped with the number of frames played before them
+    pub inputs: Vec<(u64, Input)>,
+    pub frames: u64,
+}
+
+impl Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let settings = &self.settings;
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        writeln!(f, "seed {}", settings.seed.unwrap_or_default())?;
+        writeln!(f, "mode {}", settings.mode.name())?;
+        writeln!(f, "randomizer {}", settings.randomizer.name())?;
+        writeln!(f, "previews {}", settings.previews)?;
+        writeln!(f, "das {}", settings.handling.das)?;
+        writeln!(f, "arr {}", settings.handling.arr)?;
+        writeln!(f, "sdf {}", settings.handling.sdf)?;
+        writeln!(f, "marathon_lines {}", settings.marathon_lines)?;
+        writeln!(f, "zen_speed {}", settings.zen_speed)?;
+        writeln!(f, "dig_rows {}", settings.dig_rows)?;
+        writeln!(f, "garbage {}", settings.garbage.name())?;
+        for (frame, input) in &self.inputs {
+            match input {
+                Input::Press(key) => writeln!(f, "{} press {}", frame, key.name())?,
+                Input::Release(key) => writeln!(f, "{} release {}", frame, key.name())?,
+                Input::Tick => (),
+            }
+        }
+        writeln!(f, "end {}", self.frames)
+    }
+}
+
+impl Replay {
+    pub fn parse(text: &str) -> Result<Replay, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        match lines.next() {
+            Some((_, header)) if header == format!("{} {}", HEADER, VERSION) => (),
+            Some((_, header)) if header.starts_with(HEADER) => {
+                return Err(format!("unsupported replay version '{}'", header))
+            }
+            _ => return Err(String::from("not a replay file")),
+        }
+        let mut settings = Settings::default();
+        let mut inputs = Vec::new();
+        let mut frames = None;
+        for (i, line) in lines {
+            let error = |msg: String| format!("line {}: {}", i, msg);
+            let number = |word: &str| {
+                word.parse()
+                    .map_err(|_| error(format!("'{}' is not a number", word)))
+            };
+            let key = |name: &str| {
+                KeyEvent::from_name(name).ok_or(error(format!("unknown key '{}'", name)))
+            };
+            match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                ["seed", seed] => settings.seed = Some(number(seed)?),
+                ["mode", name] => {
+                    settings.mode = GameMode::from_name(name)
+                        .ok_or(error(format!("unknown mode '{}'", name)))?
+                }
+                ["randomizer", name] => {
+                    settings.randomizer = RandomizerKind::from_name(name)
+                        .ok_or(error(format!("unknown randomizer '{}'", name)))?
+                }
+                ["previews", previews] => {
+                    settings.previews = number(previews)?.clamp(1, MAX_PREVIEWS as u64) as usize
+                }
+                ["das", das] => settings.handling.das = number(das)? as u32,
+                ["arr", arr] => settings.handling.arr = number(arr)? as u32,
+                ["sdf", sdf] => settings.handling.sdf = (number(sdf)? as u32).max(1),
+                ["marathon_lines", lines] => {
+                    settings.marathon_lines = (number(lines)? as usize).max(1)
+                }
+                ["zen_speed", speed] => {
+                    settings.zen_speed = (number(speed)? as usize).clamp(1, MAX_ZEN_SPEED)
+                }
+                ["dig_rows", rows] => {
+                    settings.dig_rows = (number(rows)? as usize).clamp(1, MAX_DIG_ROWS)
+                }
+                ["garbage", name] => {
+                    settings.garbage = GarbageKind::from_name(name)
+                        .ok_or(error(format!("unknown garbage '{}'", name)))?
+                }
+                [frame, "press", name] => inputs.push((number(frame)?, Input::Press(key(name)?))),
+                [frame, "release", name] => {
+                    inputs.push((number(frame)?, Input::Release(key(name)?)))
+                }
+                ["end", end] => frames = Some(number(end)?),
+                _ => return Err(error(format!("unexpected '{}'", line))),
+            }
+        }
+        if settings.seed.is_none() {
+            return Err(String::from("the replay has no seed"));
+        }
+        let Some(frames) = frames else {
+            return Err(String::from("the replay has no end, it may be cut short"));
+        };
+        Ok(Replay {
+            settings,
+            inputs,
+            frames,
+        })
+    }
+}
+
+// Plays a replay back one frame at a time
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Playback {
+    replay: Replay,
+    tetris: Tetris,
+    next: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        let mut tetris = Tetris::new(replay.settings);
+        tetris.step(Input::Press(KeyEvent::Play));
+        Playback {
+            replay,
+            tetris,
+            next: 0,
+        }
+    }
+
+    pub fn tetris(&self) -> &Tetris {
+        &self.tetris
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.replay.inputs.len()
+            && (self.tetris.frame() >= self.replay.frames
+                || *self.tetris.state() != GameState::Playing)
+    }
+
+    // Feeds the inputs of the current frame, then plays the frame
+    pub fn advance(&mut self) {
+        while let Some((frame, input)) = self.replay.inputs.get(self.next) {
+            if *frame > self.tetris.frame() {
+                break;
+            }
+            self.tetris.step(input.clone());
+            self.next += 1;
+        }
+        if !self.is_finished() {
+            self.tetris.step(Input::Tick);
+        }
+        // A replay only shows the game, nothing reacts to its events
+        self.tetris.events().for_each(drop);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_round_trip() {
+        let mut tetris = Tetris::new(Settings {
+            seed: Some(7),
+            ..Default::default()
+        });
+        tetris.step(Input::Press(KeyEvent::Play));
+        let inputs = [
+            (5, Input::Press(KeyEvent::Left)),
+            (30, Input::Release(KeyEvent::Left)),
+            (31, Input::Press(KeyEvent::RotateClockwise)),
+            (40, Input::Press(KeyEvent::HardDrop)),
+            (41, Input::Press(KeyEvent::Pause)),
+            (41, Input::Press(KeyEvent::Play)),
+            (90, Input::Press(KeyEvent::Hold)),
+        ];
+        for frame in 0..200 {
+            for (_, input) in inputs.iter().filter(|(at, _)| *at == frame) {
+                tetris.step(input.clone());
+            }
+            tetris.step(Input::Tick);
+        }
+        let replay = Replay::parse(&tetris.replay().to_string()).unwrap();
+        assert_eq!(replay, tetris.replay());
+        assert_eq!(replay.inputs.len(), inputs.len());
+
+        let mut playback = Playback::new(replay);
+        while !playback.is_finished() {
+            playback.advance();
+        }
+        assert_eq!(playback.tetris().frame(), 200);
+        assert_eq!(playback.tetris().board(), tetris.board());
+        assert_eq!(playback.tetris().current_block(), tetris.current_block());
+        assert_eq!(playback.tetris().points(), tetris.points());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Replay::parse("hello"),
+            Err(String::from("not a replay file"))
+        );
+        assert_eq!(
+            Replay::parse("tetris-tui-vanilla replay 9"),
+            Err(String::from(
+                "unsupported replay version 'tetris-tui-vanilla replay 9'"
+            ))
+        );
+        assert_eq!(
+            Replay::parse("tetris-tui-vanilla replay 1\nseed 1\n3 press jump"),
+            Err(String::from("line 3: unknown key 'jump'"))
+        );
+        assert!(Replay::parse("tetris-tui-vanilla replay 1\nseed 1").is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use std::{cmp::Ordering, fs, io, path::PathBuf, time::SystemTime};
+
+use tetris::{format_time, GameMode, RankBy, FPS, SPLIT_LINES};
+
+use crate::config;
+
+const SCORES_FILE: &str = "scores.txt";
+const TOP_SCORES: usize = 10;
+pub const MAX_NAME: usize = 12;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Score {
+    pub mode: GameMode,
+    pub name: String,
+    pub points: usize,
+    pub lines: usize,
+    pub level: usize,
+    pub frames: u64,
+    pub date: String,
+    // Split times of a finished game, for modes with a line goal
+    pub splits: Vec<u64>,
+}
+
+impl Score {
+    // One tab separated line per score, with comma separated split times last
+    fn parse(line: &str) -> Option<Score> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [mode, name, points, lines, level, frames, date, splits] = fields.as_slice() else {
+            return None;
+        };
+        Some(Score {
+            mode: GameMode::from_name(mode)?,
+            name: name.to_string(),
+            points: points.parse().ok()?,
+            lines: lines.parse().ok()?,
+            level: level.parse().ok()?,
+            frames: frames.parse().ok()?,
+            date: date.to_string(),
+            splits: splits
+                .split(',')
+                .filter(|split| !split.is_empty())
+                .map(|split| split.parse().ok())
+                .collect::<Option<Vec<u64>>>()?,
+        })
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.mode.name(),
+            self.name,
+            self.points,
+            self.lines,
+            self.level,
+            self.frames,
+            self.date,
+            self.splits
+                .iter()
+                .map(|split| split.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+
+    // Higher points first, then the faster game, or the other way round for races. Survival
+    // ranks the longest game first
+    fn compare(&self, other: &Score) -> Ordering {
+        let points = other.points.cmp(&self.points);
+        let time = self.frames.cmp(&other.frames);
+        match self.mode.rank_by() {
+            RankBy::Points => points.then(time),
+            RankBy::Time => time.then(points),
+            RankBy::Survival => time.reverse().then(points),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct HighScores {
+    scores: Vec<Score>,
+}
+
+impl HighScores {
+    // A missing file is an empty table, damaged lines are dropped. Any other error is returned so
+    // the file is never saved over
+    pub fn load() -> io::Result<HighScores> {
+        let Some(path) = scores_path() else {
+            return Ok(HighScores::default());
+        };
+        match fs::read(path) {
+            Ok(bytes) => Ok(HighScores::parse(&bytes)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HighScores::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Invalid UTF-8 only damages the lines it is in
+    fn parse(bytes: &[u8]) -> HighScores {
+        let mut high_scores = HighScores {
+            scores: String::from_utf8_lossy(bytes)
+                .lines()
+                .filter_map(Score::parse)
+                .collect(),
+        };
+        high_scores.trim();
+        high_scores
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = scores_path().ok_or(io::Error::other("no data directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: String = self
+            .scores
+            .iter()
+            .map(|score| score.to_line() + "\n")
+            .collect();
+        // Write a copy first so a crash never leaves half a file behind
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(tmp, path)
+    }
+
+    // Races and Survival rank any game by its time, other modes need points
+    pub fn qualifies(&self, score: &Score) -> bool {
+        (score.mode.rank_by() != RankBy::Points || score.points > 0)
+            && self.rank(score) < TOP_SCORES
+    }
+
+    pub fn best(&self, mode: GameMode) -> Option<&Score> {
+        self.top(mode).next()
+    }
+
+    // Adds the score and returns its place in the table, starting at 1
+    pub fn insert(&mut self, score: Score) -> Option<usize> {
+        let rank = self.rank(&score);
+        if rank >= TOP_SCORES {
+            return None;
+        }
+        self.scores.push(score);
+        self.trim();
+        Some(rank + 1)
+    }
+
+    pub fn table(&self, mode: GameMode) -> Vec<String> {
+        let mut table = vec![
+            format!("HIGH SCORES - {}", mode.name().to_uppercase()),
+            String::new(),
+            format!(
+                "{:>2}  {:<width$}  {:>8}  {:>5}  {:>5}  {:>10}  {}",
+                "#",
+                "NAME",
+                "POINTS",
+                "LINES",
+                "LEVEL",
+                "TIME",
+                "DATE",
+                width = MAX_NAME
+            ),
+        ];
+        let scores: Vec<&Score> = self.top(mode).collect();
+        if scores.is_empty() {
+            table.push(String::from("No scores yet"));
+        }
+        for (i, score) in scores.into_iter().enumerate() {
+            table.push(format!(
+                "{:>2}  {:<width$}  {:>8}  {:>5}  {:>5}  {:>10}  {}",
+                i + 1,
+                score.name,
+                score.points,
+                score.lines,
+                score.level,
+                format_time(score.frames),
+                score.date,
+                width = MAX_NAME
+            ));
+        }
+        table
+    }
+
+    fn top(&self, mode: GameMode) -> impl Iterator<Item = &Score> {
+        self.scores.iter().filter(move |score| score.mode == mode)
+    }
+
+    fn rank(&self, score: &Score) -> usize {
+        self.top(score.mode)
+            .filter(|other| other.compare(score).is_le())
+            .count()
+    }
+
+    // Keeps every mode sorted and at most TOP_SCORES long
+    fn trim(&mut self) {
+        self.scores
+            .sort_by(|a, b| a.mode.name().cmp(b.mode.name()).then_with(|| a.compare(b)));
+        let mut kept: Vec<Score> = Vec::new();
+        for score in self.scores.drain(..) {
+            if kept.iter().filter(|other| other.mode == score.mode).count() < TOP_SCORES {
+                kept.push(score);
+            }
+        }
+        self.scores = kept;
+    }
+}
+
+// Split times like "10: 0:15.250 -0.433", ahead of the best game when negative
+pub fn splits_line(splits: &[u64], best: &[u64]) -> String {
+    splits
+        .iter()
+        .enumerate()
+        .map(|(i, &frames)| {
+            let split = format!("{}: {}", (i + 1) * SPLIT_LINES, format_time(frames));
+            match best.get(i) {
+                Some(&best) => {
+                    let millis = (frames as i64 - best as i64) * 1000 / FPS as i64;
+                    let sign = if millis < 0 { '-' } else { '+' };
+                    let millis = millis.unsigned_abs();
+                    format!("{} {}{}.{:03}", split, sign, millis / 1000, millis % 1000)
+                }
+                None => split,
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("  ")
+}
+
+fn scores_path() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join(SCORES_FILE))
+}
+
+// Today's date as YYYY-MM-DD, in UTC
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let (year, month, day) = civil_date(seconds as i64 / 86400);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian date, from Howard Hinnant's algorithm
+fn civil_date(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(name: &str, points: usize) -> Score {
+        Score {
+            mode: GameMode::Endless,
+            name: String::from(name),
+            points,
+            lines: 10,
+            level: 2,
+            frames: 3600,
+            date: String::from("2026-10-16"),
+            splits: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_insert_and_rank() {
+        let mut high_scores = HighScores::default();
+        for points in 1..=TOP_SCORES {
+            assert_eq!(high_scores.insert(score("low", points * 100)), Some(1));
+        }
+        assert!(!high_scores.qualifies(&score("late", 50)));
+        assert_eq!(high_scores.insert(score("mid", 550)), Some(6));
+        assert_eq!(high_scores.top(GameMode::Endless).count(), TOP_SCORES);
+        assert_eq!(
+            high_scores.top(GameMode::Endless).last().unwrap().points,
+            200
+        );
+        let table = high_scores.table(GameMode::Endless);
+        assert!(table[8].starts_with(" 6  mid"));
+        assert!(table[8].ends_with("1:00.000  2026-10-16"));
+    }
+
+    #[test]
+    fn test_parse_skips_damaged_lines() {
+        let text = format!(
+            "{}\ngarbage\nendless\tbob\tnot a number\t1\t1\t1\t2026-01-01\n\u{0}\u{1}\n{}\n",
+            score("alice", 300).to_line(),
+            score("carol", 500).to_line(),
+        );
+        let mut bytes = text.into_bytes();
+        bytes.extend(b"\xff\xfe\n");
+        let high_scores = HighScores::parse(&bytes);
+        let names: Vec<&str> = high_scores
+            .top(GameMode::Endless)
+            .map(|score| score.name.as_str())
+            .collect();
+        assert_eq!(names, ["carol", "alice"]);
+    }
+
+    #[test]
+    fn test_sprint_ranks_by_time() {
+        let sprint = |name: &str, frames: u64, lines: usize| Score {
+            mode: GameMode::Sprint,
+            lines,
+            frames,
+            splits: vec![frames / 4, frames / 2],
+            ..score(name, 1000)
+        };
+        let mut high_scores = HighScores::default();
+        assert!(high_scores.qualifies(&Score {
+            points: 0,
+            ..sprint("pointless", 9000, 40)
+        }));
+        high_scores.insert(sprint("slow", 6000, 40));
+        high_scores.insert(sprint("fast", 4800, 40));
+        let text: String = high_scores
+            .scores
+            .iter()
+            .map(|score| score.to_line() + "\n")
+            .collect();
+        let high_scores = HighScores::parse(text.as_bytes());
+        let best = high_scores.best(GameMode::Sprint).unwrap();
+        assert_eq!(best.name, "fast");
+        assert_eq!(best.splits, [1200, 2400]);
+        assert_eq!(
+            splits_line(&[1230, 2400, 3000], &best.splits),
+            "10: 0:20.500 +0.500  20: 0:40.000 +0.000  30: 0:50.000"
+        );
+        assert_eq!(splits_line(&[1170], &best.splits), "10: 0:19.500 -0.500");
+    }
+
+    #[test]
+    fn test_survival_ranks_by_frames() {
+        let survival = |name: &str, points: usize, frames: u64| Score {
+            mode: GameMode::Survival,
+            frames,
+            ..score(name, points)
+        };
+        let mut high_scores = HighScores::default();
+        assert!(high_scores.qualifies(&survival("pointless", 0, 600)));
+        high_scores.insert(survival("short", 5000, 3600));
+        high_scores.insert(survival("long", 100, 7200));
+        high_scores.insert(survival("tied", 300, 7200));
+        let names: Vec<&str> = high_scores
+            .top(GameMode::Survival)
+            .map(|score| score.name.as_str())
+            .collect();
+        assert_eq!(names, ["tied", "long", "short"]);
+    }
+
+    #[test]
+    fn test_civil_date() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(19_000), (2022, 1, 8));
+        assert_eq!(civil_date(20_742), (2026, 10, 16));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+    }
+}
@@ -0,0 +1,175 @@
+#[derive(Clone, Debug, PartialEq, Eq, Copy, Default)]
+pub enum Spin {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct LineClear {
+    pub lines: usize,
+    pub spin: Spin,
+    pub back_to_back: bool,
+    pub combo: usize,
+    pub perfect: bool,
+}
+
+impl LineClear {
+    // Clears that start or keep a back-to-back chain
+    pub fn is_difficult(&self) -> bool {
+        self.lines == 4 || (self.lines > 0 && self.spin != Spin::None)
+    }
+
+    pub fn points(&self, level: usize) -> usize {
+        let points = match (self.spin, self.lines) {
+            (Spin::None, 1) => 100,
+            (Spin::None, 2) => 300,
+            (Spin::None, 3) => 500,
+            (Spin::None, 4) => 800,
+            (Spin::Mini, 0) => 100,
+            (Spin::Mini, 1) => 200,
+            (Spin::Mini, 2) => 400,
+            (Spin::Full, 0) => 400,
+            (Spin::Full, 1) => 800,
+            (Spin::Full, 2) => 1200,
+            (Spin::Full, 3) => 1600,
+            (_, _) => 0,
+        };
+        let points = if self.back_to_back {
+            points * 3 / 2
+        } else {
+            points
+        };
+        let bonus = match (self.perfect, self.lines) {
+            (false, _) => 0,
+            (true, 1) => 800,
+            (true, 2) => 1200,
+            (true, 3) => 1800,
+            (true, _) if self.back_to_back => 3200,
+            (true, _) => 2000,
+        };
+        (points + 50 * self.combo + bonus) * level
+    }
+
+    pub fn label(&self) -> Vec<String> {
+        let lines = match self.lines {
+            0 => "",
+            1 => "SINGLE",
+            2 => "DOUBLE",
+            3 => "TRIPLE",
+            _ => "TETRIS",
+        };
+        let name = match self.spin {
+            Spin::None => String::from(lines),
+            Spin::Mini => format!("MINI T-SPIN {}", lines),
+            Spin::Full => format!("T-SPIN {}", lines),
+        };
+        let name = name.trim_end();
+        let mut label = Vec::new();
+        if self.perfect {
+            label.push(String::from("PERFECT CLEAR"));
+        }
+        label.push(if self.back_to_back {
+            format!("B2B {}", name)
+        } else {
+            String::from(name)
+        });
+        if self.combo > 0 {
+            label.push(format!("COMBO x{}", self.combo));
+        }
+        label
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Statistics {
+    // Singles, doubles, triples and tetrises, T-spins included
+    pub clears: [usize; 4],
+    pub t_spins: usize,
+    pub perfect_clears: usize,
+}
+
+impl Statistics {
+    pub fn record(&mut self, clear: &LineClear) {
+        if clear.lines > 0 {
+            self.clears[clear.lines.min(4) - 1] += 1;
+        }
+        if clear.spin != Spin::None {
+            self.t_spins += 1;
+        }
+        if clear.perfect {
+            self.perfect_clears += 1;
+        }
+    }
+
+    // One line per kind of clear, for the end screen
+    pub fn summary(&self) -> Vec<String> {
+        let [singles, doubles, triples, tetrises] = self.clears;
+        vec![
+            format!("Singles: {}", singles),
+            format!("Doubles: {}", doubles),
+            format!("Triples: {}", triples),
+            format!("Tetrises: {}", tetrises),
+            format!("T-spins: {}", self.t_spins),
+            format!("Perfect clears: {}", self.perfect_clears),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_points() {
+        let tetris = LineClear {
+            lines: 4,
+            ..Default::default()
+        };
+        assert_eq!(tetris.points(2), 1600);
+        let back_to_back = LineClear {
+            back_to_back: true,
+            combo: 2,
+            ..tetris
+        };
+        assert_eq!(back_to_back.points(1), 1300);
+        assert_eq!(back_to_back.label(), ["B2B TETRIS", "COMBO x2"]);
+    }
+
+    #[test]
+    fn test_t_spin_points() {
+        let double = LineClear {
+            lines: 2,
+            spin: Spin::Full,
+            ..Default::default()
+        };
+        assert!(double.is_difficult());
+        assert_eq!(double.points(1), 1200);
+        assert_eq!(double.label(), ["T-SPIN DOUBLE"]);
+        let mini = LineClear {
+            lines: 0,
+            spin: Spin::Mini,
+            ..Default::default()
+        };
+        assert!(!mini.is_difficult());
+        assert_eq!(mini.points(3), 300);
+        assert_eq!(mini.label(), ["MINI T-SPIN"]);
+    }
+
+    #[test]
+    fn test_perfect_clear_points() {
+        let clear = LineClear {
+            lines: 4,
+            perfect: true,
+            ..Default::default()
+        };
+        assert_eq!(clear.points(1), 2800);
+        let back_to_back = LineClear {
+            back_to_back: true,
+            ..clear
+        };
+        assert_eq!(back_to_back.points(1), 1200 + 3200);
+        assert_eq!(back_to_back.label(), ["PERFECT CLEAR", "B2B TETRIS"]);
+    }
+}